@@ -0,0 +1,216 @@
+use std::{collections::BTreeMap, fmt::Display, fs, io, path::{Path, PathBuf}};
+
+use serde_json::Value;
+
+use crate::{data::{AttachmentData, Thread}, export, net};
+
+/// Everything that can stop a thread from being archived
+#[derive(Debug)]
+pub enum Error {
+	// the thread couldn't be loaded
+	Net(net::Error),
+	// the archive couldn't be read or written
+	Io(io::Error),
+	// the thread (or the already archived copy) isn't in the format we expected
+	Decode(serde_json::Error),
+}
+
+impl std::error::Error for Error {}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Error::Net(e) => write!(f, "couldn't load the thread: {}", e),
+			Error::Io(e) => write!(f, "couldn't write the archive: {}", e),
+			Error::Decode(e) => write!(f, "couldn't read the thread: {}", e),
+		}
+	}
+}
+
+impl From<net::Error> for Error {
+	fn from(e: net::Error) -> Self {
+		Error::Net(e)
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Error::Io(e)
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(e: serde_json::Error) -> Self {
+		Error::Decode(e)
+	}
+}
+
+/// What an archiver run did
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+	// posts that weren't in the archive before
+	pub new_posts:  usize,
+	pub downloaded: usize,
+	// files that were already archived (and passed verification, if they can be verified)
+	pub skipped:    usize,
+	// (file name, what went wrong)
+	pub failed:     Vec<(String, String)>,
+}
+
+/// Where a thread is archived: `<root>/<board>/<thread>/`, with the API's JSON in `thread.json`,
+/// a page for browsing it offline in `index.html`, attachments in `images/` and thumbnails in `thumbs/`
+pub fn thread_dir(root: &Path, board: &str, no: isize) -> PathBuf {
+	root.join(board).join(no.to_string())
+}
+
+/// Base64 of the MD5 of `data`, the format of `AttachmentData::md5`
+pub fn md5_base64(data: &[u8]) -> String {
+	base64::encode(md5::compute(data).0)
+}
+
+/// Adds the posts of a freshly loaded thread to the archived copy.
+/// Posts deleted since the last run are kept, posts in both are replaced by their newer version.
+/// Returns the merged thread and how many of it's posts weren't archived before.
+pub fn merge(old: Option<&Value>, new: &Value) -> (Value, usize) {
+	let post_no = |post: &Value| post["no"].as_i64().unwrap_or(0);
+	let mut posts: BTreeMap<i64, Value> = old
+		.and_then(|old| old["posts"].as_array())
+		.map(|posts| posts.iter().map(|post| (post_no(post), post.clone())).collect())
+		.unwrap_or_default();
+	let mut new_posts = 0;
+	for post in new["posts"].as_array().into_iter().flatten() {
+		if posts.insert(post_no(post), post.clone()).is_none() {
+			new_posts += 1;
+		}
+	}
+	let mut merged = new.clone();
+	merged["posts"] = Value::Array(posts.into_values().collect());
+	(merged, new_posts)
+}
+
+/// The files belonging to an attachment: path relative to the thread directory, URL, and MD5 if it can be verified
+fn attachment_files(board: &str, attachment: &AttachmentData) -> [(PathBuf, String, Option<String>); 2] {
+	let tim = attachment.tim;
+	[
+		(
+			Path::new("images").join(format!("{}{}", tim, attachment.ext)),
+			net::image_url(board, tim, &attachment.ext),
+			Some(attachment.md5.clone()),
+		),
+		// 4chan doesn't publish hashes of thumbnails
+		(Path::new("thumbs").join(format!("{}s.jpg", tim)), net::thumbnail_url(board, tim), None),
+	]
+}
+
+/// Writes a temporary file first and renames it, so an interrupted run never leaves a truncated file that looks archived
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+	let tmp = path.with_extension("part");
+	fs::write(&tmp, data).and_then(|_| fs::rename(&tmp, path))
+}
+
+/// Downloads `url` to `path`, unless a copy that passes verification is already there.
+/// Returns whether anything was downloaded.
+fn fetch_file(path: &Path, url: &str, md5: Option<&str>) -> Result<bool, String> {
+	if let Ok(existing) = fs::read(path) {
+		match md5 {
+			Some(md5) if md5_base64(&existing) != md5 => log::info!("{} is corrupted, downloading it again", path.display()),
+			_ => return Ok(false),
+		}
+	}
+	let data = net::download(url, net::Priority::User).map_err(|e| e.to_string())?;
+	if let Some(md5) = md5 {
+		let actual = md5_base64(&data);
+		if actual != md5 {
+			return Err(format!("MD5 mismatch, expected {} but got {}", md5, actual));
+		}
+	}
+	write_atomic(path, &data).map_err(|e| e.to_string())?;
+	Ok(true)
+}
+
+/// Archives a thread into `thread_dir(root, board, no)`, or brings an existing archive up to date.
+/// Only posts and files that aren't archived yet are downloaded.
+pub fn archive_thread(root: &Path, board: &str, no: isize) -> Result<Report, Error> {
+	let dir = thread_dir(root, board, no);
+	let new: Value = serde_json::from_slice(&net::get_thread_json(board, no, net::Priority::User)?)?;
+	let json_path = dir.join("thread.json");
+	let old: Option<Value> = match fs::read(&json_path) {
+		Ok(raw) => Some(serde_json::from_slice(&raw)?),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+		Err(e) => return Err(e.into()),
+	};
+	let (merged, new_posts) = merge(old.as_ref(), &new);
+
+	fs::create_dir_all(dir.join("images"))?;
+	fs::create_dir_all(dir.join("thumbs"))?;
+	write_atomic(&json_path, &serde_json::to_vec_pretty(&merged)?)?;
+
+	let mut report = Report { new_posts, ..Report::default() };
+	let thread: Thread = serde_json::from_value(merged)?;
+	write_atomic(&dir.join("index.html"), export::html::thread_to_html(board, &thread).as_bytes())?;
+	for attachment in thread.posts.iter().filter_map(|post| post.attachment.as_ref()) {
+		if attachment.filedeleted {
+			continue;
+		}
+		for (file, url, md5) in attachment_files(board, attachment) {
+			match fetch_file(&dir.join(&file), &url, md5.as_deref()) {
+				Ok(true) => report.downloaded += 1,
+				Ok(false) => report.skipped += 1,
+				Err(e) => report.failed.push((file.display().to_string(), e)),
+			}
+		}
+	}
+	Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use serde_json::Value;
+
+	use super::{fetch_file, md5_base64, merge};
+
+	#[test]
+	fn test_md5_base64() {
+		assert_eq!(md5_base64(b""), "1B2M2Y8AsgTpgAmY7PhCfg==");
+		assert_eq!(md5_base64(b"The quick brown fox jumps over the lazy dog"), "nhB9nTcrtoJr2B01QqQZ1g==");
+	}
+
+	#[test]
+	fn test_merge() {
+		let dummy: Value = serde_json::from_str(&fs::read_to_string("assets/test/dummy.json").unwrap()).unwrap();
+		let (merged, new_posts) = merge(None, &dummy);
+		assert_eq!(new_posts, 3);
+		assert_eq!(merged, dummy);
+
+		// the second post was deleted, and a new one arrived
+		let mut update = dummy.clone();
+		let posts = update["posts"].as_array_mut().unwrap();
+		posts.remove(1);
+		let mut new_post = posts[1].clone();
+		new_post["no"] = 570400.into();
+		posts.push(new_post);
+		posts[0]["replies"] = 3.into();
+
+		let (merged, new_posts) = merge(Some(&dummy), &update);
+		assert_eq!(new_posts, 1);
+		let numbers: Vec<i64> = merged["posts"].as_array().unwrap().iter().map(|post| post["no"].as_i64().unwrap()).collect();
+		assert_eq!(numbers, vec![570368, 570370, 570371, 570400]);
+		assert_eq!(merged["posts"][0]["replies"], 3);
+	}
+
+	#[test]
+	fn test_verified_files_are_skipped() {
+		let dir = std::env::temp_dir().join(format!("fourchan-tui-archive-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("1546293948883.png");
+		fs::write(&path, b"").unwrap();
+		// the URL is never requested since the file is already there
+		assert_eq!(fetch_file(&path, "http://127.0.0.1:9/unreachable", Some("1B2M2Y8AsgTpgAmY7PhCfg==")), Ok(false));
+		assert_eq!(fetch_file(&path, "http://127.0.0.1:9/unreachable", None), Ok(false));
+		// a corrupted file is downloaded again
+		assert!(fetch_file(&path, "http://127.0.0.1:9/unreachable", Some("nhB9nTcrtoJr2B01QqQZ1g==")).is_err());
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
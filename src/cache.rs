@@ -0,0 +1,271 @@
+use std::{collections::HashMap, fs, io, path::{Path, PathBuf}, process, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, time::{Duration, SystemTime}};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// What we remember about a cached response besides it's body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheMeta {
+	pub url:           String,
+	pub etag:          Option<String>,
+	pub last_modified: Option<String>,
+	pub size:          u64,
+	// unix timestamp in milliseconds, used to evict the least recently used entries first
+	pub last_used:     i64,
+}
+
+// how much newer a use has to be before it's written back to disk, the in-memory index is always exact
+const LAST_USED_GRANULARITY: i64 = 60 * 1000;
+// temporary files older than this are left over from interrupted writes
+const STALE_TMP_AGE: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+	pub body: Vec<u8>,
+	pub meta: CacheMeta,
+}
+
+/// A size-limited cache of HTTP responses on disk, keyed by URL.
+/// Every entry is stored as a `<key>.body` file with a `<key>.json` file holding it's `CacheMeta`.
+#[derive(Debug)]
+pub struct DiskCache {
+	dir:      PathBuf,
+	max_size: u64,
+	// size and last use of every entry, so eviction doesn't have to scan the directory
+	index:    Mutex<HashMap<String, (u64, i64)>>,
+}
+
+/// Stable key for a URL (64-bit FNV-1a), usable as a file name
+fn key(url: &str) -> String {
+	let mut hash: u64 = 0xcbf29ce484222325;
+	for byte in url.bytes() {
+		hash ^= byte as u64;
+		hash = hash.wrapping_mul(0x100000001b3);
+	}
+	format!("{:016x}", hash)
+}
+
+fn now_millis() -> i64 {
+	Utc::now().timestamp_millis()
+}
+
+/// Writes `contents` to a temporary file first so readers never see a half written file
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+	static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+	// the UI and the watch daemon can share a cache directory, so the counter alone isn't unique
+	let tmp = path.with_extension(format!("tmp{}-{}", process::id(), TMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+	fs::write(&tmp, contents)?;
+	fs::rename(&tmp, path)
+}
+
+impl DiskCache {
+	/// Opens (or creates) a cache in `dir` that holds at most `max_size` bytes of response bodies
+	pub fn new(dir: impl Into<PathBuf>, max_size: u64) -> io::Result<DiskCache> {
+		let dir = dir.into();
+		fs::create_dir_all(&dir)?;
+		let mut index = HashMap::new();
+		for entry in fs::read_dir(&dir)? {
+			let path = entry?.path();
+			if path.extension().is_some_and(|ext| ext == "json") {
+				let meta = fs::read(&path).ok().and_then(|raw| serde_json::from_slice::<CacheMeta>(&raw).ok());
+				match (meta, path.file_stem().and_then(|s| s.to_str())) {
+					(Some(meta), Some(key)) => { index.insert(key.to_string(), (meta.size, meta.last_used)); }
+					// unreadable metadata makes the body useless
+					_ => {
+						let _ = fs::remove_file(path.with_extension("body"));
+						let _ = fs::remove_file(&path);
+					}
+				}
+			}
+		}
+		let cache = DiskCache { dir, max_size, index: Mutex::new(index) };
+		cache.sweep_tmp_files();
+		cache.evict();
+		Ok(cache)
+	}
+
+	fn paths(&self, key: &str) -> (PathBuf, PathBuf) {
+		(self.dir.join(format!("{}.body", key)), self.dir.join(format!("{}.json", key)))
+	}
+
+	/// Looks up the cached response for `url`, marking it as recently used
+	pub fn get(&self, url: &str) -> Option<CacheEntry> {
+		self.get_at(url, now_millis())
+	}
+
+	/// `get`, with `now` as the time of use
+	fn get_at(&self, url: &str, now: i64) -> Option<CacheEntry> {
+		let key = key(url);
+		if !self.index.lock().unwrap().contains_key(&key) {
+			return None;
+		}
+		let (body_path, meta_path) = self.paths(&key);
+		let mut meta: CacheMeta = serde_json::from_slice(&fs::read(&meta_path).ok()?).ok()?;
+		// guard against hash collisions
+		if meta.url != url {
+			return None;
+		}
+		let body = fs::read(&body_path).ok()?;
+		if let Some(entry) = self.index.lock().unwrap().get_mut(&key) {
+			entry.1 = now;
+		}
+		// only the order across restarts depends on this, so it doesn't need a write on every hit
+		if now - meta.last_used >= LAST_USED_GRANULARITY {
+			meta.last_used = now;
+			if let Ok(raw) = serde_json::to_vec(&meta) {
+				let _ = write_atomic(&meta_path, &raw);
+			}
+		}
+		Some(CacheEntry { body, meta })
+	}
+
+	/// Stores a response, evicting the least recently used entries if the cache grows too large
+	pub fn put(&self, url: &str, body: &[u8], etag: Option<String>, last_modified: Option<String>) -> io::Result<()> {
+		self.put_at(url, body, etag, last_modified, now_millis())
+	}
+
+	/// `put`, with `now` as the time of use
+	fn put_at(&self, url: &str, body: &[u8], etag: Option<String>, last_modified: Option<String>, now: i64) -> io::Result<()> {
+		let key = key(url);
+		let (body_path, meta_path) = self.paths(&key);
+		let meta = CacheMeta {
+			url: url.to_string(),
+			etag,
+			last_modified,
+			size: body.len() as u64,
+			last_used: now,
+		};
+		write_atomic(&body_path, body)?;
+		write_atomic(&meta_path, &serde_json::to_vec(&meta)?)?;
+		self.index.lock().unwrap().insert(key, (meta.size, meta.last_used));
+		self.evict();
+		Ok(())
+	}
+
+	/// Total size of all cached bodies
+	pub fn size(&self) -> u64 {
+		self.index.lock().unwrap().values().map(|(size, _)| size).sum()
+	}
+
+	fn evict(&self) {
+		let mut index = self.index.lock().unwrap();
+		let mut total: u64 = index.values().map(|(size, _)| size).sum();
+		if total <= self.max_size {
+			return;
+		}
+		self.sweep_tmp_files();
+		let mut by_age: Vec<(String, u64, i64)> = index.iter().map(|(key, &(size, used))| (key.clone(), size, used)).collect();
+		by_age.sort_by_key(|&(_, _, used)| used);
+		for (key, size, _) in by_age {
+			if total <= self.max_size {
+				break;
+			}
+			let (body_path, meta_path) = self.paths(&key);
+			let _ = fs::remove_file(meta_path);
+			let _ = fs::remove_file(body_path);
+			index.remove(&key);
+			total -= size;
+		}
+	}
+
+	/// Removes temporary files that an interrupted `write_atomic` left behind.
+	/// Recent ones are kept, another thread might still be writing them.
+	fn sweep_tmp_files(&self) {
+		let Ok(entries) = fs::read_dir(&self.dir) else { return };
+		let now = SystemTime::now();
+		for entry in entries.flatten() {
+			let path = entry.path();
+			let is_tmp = path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ext.starts_with("tmp"));
+			let modified = entry.metadata().and_then(|meta| meta.modified());
+			let stale = modified.is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() >= STALE_TMP_AGE);
+			if is_tmp && stale {
+				let _ = fs::remove_file(path);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{fs, path::PathBuf, time::{Duration, SystemTime}};
+
+	use super::{DiskCache, LAST_USED_GRANULARITY, STALE_TMP_AGE};
+
+	fn test_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("fourchan-tui-cache-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		dir
+	}
+
+	#[test]
+	fn test_roundtrip() {
+		let dir = test_dir("roundtrip");
+		let cache = DiskCache::new(&dir, 1024).unwrap();
+		assert!(cache.get("https://a.4cdn.org/po/catalog.json").is_none());
+		cache.put("https://a.4cdn.org/po/catalog.json", b"[]", Some("\"abc\"".into()), Some("Mon, 31 Dec 2018 17:05:48 GMT".into())).unwrap();
+
+		// a fresh instance finds the entry on disk
+		let cache = DiskCache::new(&dir, 1024).unwrap();
+		let entry = cache.get("https://a.4cdn.org/po/catalog.json").unwrap();
+		assert_eq!(entry.body, b"[]");
+		assert_eq!(entry.meta.etag.as_deref(), Some("\"abc\""));
+		assert_eq!(entry.meta.last_modified.as_deref(), Some("Mon, 31 Dec 2018 17:05:48 GMT"));
+		assert_eq!(cache.size(), 2);
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn test_lru_eviction() {
+		let dir = test_dir("eviction");
+		let cache = DiskCache::new(&dir, 10).unwrap();
+		cache.put_at("a", b"1234", None, None, 0).unwrap();
+		cache.put_at("b", b"1234", None, None, 1000).unwrap();
+		// using "a" makes "b" the least recently used entry
+		assert!(cache.get_at("a", 2000).is_some());
+		cache.put_at("c", b"1234", None, None, 3000).unwrap();
+		assert!(cache.get_at("b", 4000).is_none());
+		assert!(cache.get_at("a", 4000).is_some());
+		assert!(cache.get_at("c", 5000).is_some());
+		assert_eq!(cache.size(), 8);
+
+		// shrinking the limit evicts on open, going by the use times on disk
+		let cache = DiskCache::new(&dir, 4).unwrap();
+		assert_eq!(cache.size(), 4);
+		assert!(cache.get_at("c", 6000).is_some());
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn test_hit_does_not_rewrite_metadata() {
+		let dir = test_dir("hit");
+		let cache = DiskCache::new(&dir, 1024).unwrap();
+		cache.put_at("a", b"1234", None, None, 0).unwrap();
+		let meta_path = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).find(|path| path.extension().unwrap() == "json").unwrap();
+		let before = fs::read(&meta_path).unwrap();
+		assert!(cache.get_at("a", 1000).is_some());
+		assert_eq!(fs::read(&meta_path).unwrap(), before);
+
+		// a use long enough after the last one is written back
+		assert!(cache.get_at("a", LAST_USED_GRANULARITY).is_some());
+		assert_eq!(DiskCache::new(&dir, 1024).unwrap().get_at("a", LAST_USED_GRANULARITY).unwrap().meta.last_used, LAST_USED_GRANULARITY);
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn test_sweep_tmp_files() {
+		let dir = test_dir("sweep");
+		fs::create_dir_all(&dir).unwrap();
+		let stale = dir.join("0123456789abcdef.tmp100-3");
+		let fresh = dir.join("0123456789abcdef.tmp100-4");
+		fs::write(&stale, b"half").unwrap();
+		fs::write(&fresh, b"half").unwrap();
+		let file = fs::File::options().write(true).open(&stale).unwrap();
+		file.set_modified(SystemTime::now() - STALE_TMP_AGE - Duration::from_secs(1)).unwrap();
+		drop(file);
+
+		let _cache = DiskCache::new(&dir, 1024).unwrap();
+		assert!(!stale.exists());
+		assert!(fresh.exists());
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
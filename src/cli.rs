@@ -0,0 +1,252 @@
+use std::path::PathBuf;
+
+use crate::export;
+
+pub const USAGE: &str = "\
+Usage: fourchan-tui [OPTIONS]
+       fourchan-tui [OPTIONS] watch [WATCH OPTIONS] [BOARD/THREAD...]
+       fourchan-tui [OPTIONS] archive [--dir <DIR>] BOARD/THREAD...
+       fourchan-tui [OPTIONS] export [--format <FORMAT>] [--output <FILE>] BOARD/THREAD
+
+The watch command runs the thread watcher without the UI and prints every new post, reply to your
+posts and dead thread to stdout. It watches the threads given as e.g. `g/12345`, or the watch list
+if there are none.
+
+The archive command saves threads with all of their images and thumbnails, verifying the images
+against their MD5, along with an index.html for browsing them offline. Running it again on the
+same thread only downloads what's new.
+
+The export command prints a thread as Markdown, plain text or HTML.
+
+Options:
+    --config <PATH>       read settings from PATH instead of the default config file
+    --api-url <URL>       base URL of the JSON API (default https://a.4cdn.org)
+    --image-url <URL>     base URL of images and thumbnails (default https://i.4cdn.org)
+    --static-url <URL>    base URL of static content (default https://s.4cdn.org)
+    -h, --help            print this message
+
+Watch options:
+    --format <FORMAT>     `lines` (default) or `json` for one JSON object per line
+    --interval <SECONDS>  time between checks (default 30, or `watch.interval_secs` in the config)
+    --once                check every thread once and exit
+
+Archive options:
+    --dir <DIR>           where to save threads (default ~/.local/share/fourchan-tui/archive,
+                          or `archive.dir` in the config)
+
+Export options:
+    --format <FORMAT>     `markdown` (default), `text` or `html`
+    --output <FILE>       write to FILE instead of stdout
+
+The URLs can also be set with the FOURCHAN_API_URL, FOURCHAN_IMAGE_URL and FOURCHAN_STATIC_URL
+environment variables, or in the \"endpoints\" section of the config file.";
+
+/// How the watch command prints events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+	#[default]
+	Lines,
+	// JSON Lines
+	Json,
+}
+
+/// Options of the `watch` command
+#[derive(Debug, Default, PartialEq)]
+pub struct WatchArgs {
+	pub format:   OutputFormat,
+	pub interval: Option<u64>,
+	pub once:     bool,
+	// (board, thread) pairs to watch instead of the watch list
+	pub threads:  Vec<(String, isize)>,
+}
+
+/// Options of the `archive` command
+#[derive(Debug, Default, PartialEq)]
+pub struct ArchiveArgs {
+	pub dir:     Option<PathBuf>,
+	pub threads: Vec<(String, isize)>,
+}
+
+/// Options of the `export` command
+#[derive(Debug, PartialEq)]
+pub struct ExportArgs {
+	pub format: export::Format,
+	pub output: Option<PathBuf>,
+	pub thread: Option<(String, isize)>,
+}
+
+impl Default for ExportArgs {
+	fn default() -> Self {
+		ExportArgs { format: export::Format::Markdown, output: None, thread: None }
+	}
+}
+
+/// Something to do instead of starting the UI
+#[derive(Debug, PartialEq)]
+pub enum Command {
+	Watch(WatchArgs),
+	Archive(ArchiveArgs),
+	Export(ExportArgs),
+}
+
+/// Options given on the command line
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+	pub config:     Option<PathBuf>,
+	pub api_url:    Option<String>,
+	pub image_url:  Option<String>,
+	pub static_url: Option<String>,
+	pub help:       bool,
+	pub command:    Option<Command>,
+}
+
+/// Parses a `board/thread` argument, also accepting `/board/thread` and `board/thread/123`.
+/// Boards are only lowercase letters and digits, anything else (like `..`) could lead outside the archive.
+fn parse_thread(arg: &str) -> Option<(String, isize)> {
+	let mut segments = arg.split('/').filter(|s| !s.is_empty() && *s != "thread");
+	let is_board = |board: &str| board.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+	match (segments.next(), segments.next(), segments.next()) {
+		(Some(board), Some(thread), None) if is_board(board) => Some((board.to_string(), thread.parse().ok()?)),
+		_ => None,
+	}
+}
+
+/// Parses the command line arguments, not including the program name
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+	let mut parsed = Args::default();
+	let mut args = args.into_iter();
+	while let Some(arg) = args.next() {
+		// accept both `--flag value` and `--flag=value`
+		let (flag, inline_value) = match arg.find('=') {
+			Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+			_ => (arg.clone(), None),
+		};
+		let mut value = || inline_value.clone()
+			.or_else(|| args.next())
+			.ok_or_else(|| format!("{} needs a value", flag));
+		match (&*flag, parsed.command.as_mut()) {
+			("--config", _) => parsed.config = Some(PathBuf::from(value()?)),
+			("--api-url", _) => parsed.api_url = Some(value()?),
+			("--image-url", _) => parsed.image_url = Some(value()?),
+			("--static-url", _) => parsed.static_url = Some(value()?),
+			("-h" | "--help", _) => parsed.help = true,
+			("watch", None) => parsed.command = Some(Command::Watch(WatchArgs::default())),
+			("archive", None) => parsed.command = Some(Command::Archive(ArchiveArgs::default())),
+			("export", None) => parsed.command = Some(Command::Export(ExportArgs::default())),
+			("--format", Some(Command::Watch(watch))) => watch.format = match &*value()? {
+				"lines" => OutputFormat::Lines,
+				"json" => OutputFormat::Json,
+				other => return Err(format!("unknown format '{}', expected `lines` or `json`", other)),
+			},
+			("--interval", Some(Command::Watch(watch))) => {
+				let interval = value()?;
+				watch.interval = Some(interval.parse().map_err(|_| format!("invalid interval '{}'", interval))?);
+			}
+			("--once", Some(Command::Watch(watch))) => watch.once = true,
+			("--dir", Some(Command::Archive(archive))) => archive.dir = Some(PathBuf::from(value()?)),
+			("--format", Some(Command::Export(export))) => export.format = match &*value()? {
+				"markdown" | "md" => export::Format::Markdown,
+				"text" | "txt" => export::Format::Text,
+				"html" => export::Format::Html,
+				other => return Err(format!("unknown format '{}', expected `markdown`, `text` or `html`", other)),
+			},
+			("--output", Some(Command::Export(export))) => export.output = Some(PathBuf::from(value()?)),
+			(_, Some(command)) if !arg.starts_with('-') => {
+				let thread = parse_thread(&arg)
+					.ok_or_else(|| format!("'{}' isn't a thread, expected something like g/12345", arg))?;
+				match command {
+					Command::Watch(watch) => watch.threads.push(thread),
+					Command::Archive(archive) => archive.threads.push(thread),
+					Command::Export(export) if export.thread.is_none() => export.thread = Some(thread),
+					Command::Export(_) => return Err("export takes a single thread".to_string()),
+				}
+			}
+			_ => return Err(format!("unrecognized argument '{}'", arg)),
+		}
+	}
+	if !parsed.help {
+		match &parsed.command {
+			Some(Command::Archive(archive)) if archive.threads.is_empty() => return Err("archive needs at least one thread, like g/12345".to_string()),
+			Some(Command::Export(export)) if export.thread.is_none() => return Err("export needs a thread, like g/12345".to_string()),
+			_ => {}
+		}
+	}
+	Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::{ArchiveArgs, Args, Command, ExportArgs, OutputFormat, WatchArgs, parse_args};
+	use crate::export;
+
+	fn parse(args: &[&str]) -> Result<Args, String> {
+		parse_args(args.iter().map(|s| s.to_string()))
+	}
+
+	#[test]
+	fn test_parse_args() {
+		assert_eq!(parse(&[]).unwrap(), Args::default());
+		let args = parse(&["--api-url", "http://localhost:8080", "--image-url=http://localhost:8081", "--config", "test.json"]).unwrap();
+		assert_eq!(args.api_url.as_deref(), Some("http://localhost:8080"));
+		assert_eq!(args.image_url.as_deref(), Some("http://localhost:8081"));
+		assert_eq!(args.config, Some(PathBuf::from("test.json")));
+		assert!(args.static_url.is_none());
+
+		assert!(parse(&["--api-url"]).is_err());
+		assert!(parse(&["--bogus"]).is_err());
+	}
+
+	#[test]
+	fn test_parse_watch_args() {
+		let args = parse(&["--config", "test.json", "watch", "--format=json", "--once", "g/123", "/po/thread/570368"]).unwrap();
+		assert_eq!(args.config, Some(PathBuf::from("test.json")));
+		assert_eq!(args.command, Some(Command::Watch(WatchArgs {
+			format: OutputFormat::Json,
+			interval: None,
+			once: true,
+			threads: vec![("g".into(), 123), ("po".into(), 570368)],
+		})));
+
+		// watch options only exist after the command
+		assert!(parse(&["--once"]).is_err());
+		assert!(parse(&["watch", "--format", "xml"]).is_err());
+		assert!(parse(&["watch", "--interval", "soon"]).is_err());
+		assert!(parse(&["watch", "g"]).is_err());
+	}
+
+	#[test]
+	fn test_parse_archive_args() {
+		let args = parse(&["archive", "--dir", "out", "po/570368"]).unwrap();
+		assert_eq!(args.command, Some(Command::Archive(ArchiveArgs {
+			dir: Some(PathBuf::from("out")),
+			threads: vec![("po".into(), 570368)],
+		})));
+		assert!(parse(&["archive"]).is_err());
+		assert!(parse(&["archive", "--once", "po/1"]).is_err());
+		assert!(parse(&["watch", "--dir", "out"]).is_err());
+		// boards end up in paths, so they can't point anywhere else
+		assert!(parse(&["archive", "../../x/123"]).is_err());
+		assert!(parse(&["archive", "../123"]).is_err());
+		assert!(parse(&["archive", "G/123"]).is_err());
+	}
+
+	#[test]
+	fn test_parse_export_args() {
+		assert_eq!(parse(&["export", "g/1"]).unwrap().command, Some(Command::Export(ExportArgs {
+			format: export::Format::Markdown,
+			output: None,
+			thread: Some(("g".into(), 1)),
+		})));
+		let args = parse(&["export", "--format", "text", "--output=thread.txt", "po/570368"]).unwrap();
+		assert_eq!(args.command, Some(Command::Export(ExportArgs {
+			format: export::Format::Text,
+			output: Some(PathBuf::from("thread.txt")),
+			thread: Some(("po".into(), 570368)),
+		})));
+		assert!(parse(&["export"]).is_err());
+		assert!(parse(&["export", "g/1", "g/2"]).is_err());
+		assert!(parse(&["export", "--format", "pdf", "g/1"]).is_err());
+	}
+}
@@ -1,20 +1,206 @@
-use std::fmt::Debug;
+use std::{env, fmt::Debug, fs, io, path::{Path, PathBuf}, process, sync::atomic::{AtomicUsize, Ordering}};
 
 	use chrono::{DateTime, NaiveDateTime, Utc};
 	use serde::{Deserialize, Serialize};
-	#[derive(Debug, Serialize, Deserialize)]
+
+	/// Base URLs of the servers the client talks to.
+	/// These can be pointed at a local mock server or a mirror.
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	#[serde(default)]
+	pub struct Endpoints {
+		// JSON API (boards, catalogs and threads)
+		pub api: String,
+		// uploaded images and thumbnails
+		pub image: String,
+		// static site content, like the spoiler image
+		#[serde(rename = "static")]
+		pub static_content: String,
+	}
+
+	impl Default for Endpoints {
+		fn default() -> Self {
+			Endpoints {
+				api: "https://a.4cdn.org".to_string(),
+				image: "https://i.4cdn.org".to_string(),
+				static_content: "https://s.4cdn.org".to_string(),
+			}
+		}
+	}
+
+	impl Endpoints {
+		/// Overrides the endpoints with the `FOURCHAN_API_URL`, `FOURCHAN_IMAGE_URL`
+		/// and `FOURCHAN_STATIC_URL` environment variables, if they're set
+		pub fn apply_env(&mut self) {
+			let vars = [
+				("FOURCHAN_API_URL", &mut self.api),
+				("FOURCHAN_IMAGE_URL", &mut self.image),
+				("FOURCHAN_STATIC_URL", &mut self.static_content),
+			];
+			for (var, endpoint) in vars {
+				if let Ok(url) = env::var(var) {
+					*endpoint = url;
+				}
+			}
+		}
+	}
+
+	/// Settings of the on-disk HTTP cache
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	#[serde(default)]
+	pub struct CacheConfig {
+		pub enabled: bool,
+		// the least recently used responses are evicted once the cache grows past this
+		pub max_size_mb: u64,
+		// defaults to `cache_dir()`
+		pub dir: Option<PathBuf>,
+	}
+
+	impl Default for CacheConfig {
+		fn default() -> Self {
+			CacheConfig {
+				enabled: true,
+				max_size_mb: 256,
+				dir: None,
+			}
+		}
+	}
+
+	/// Terminal escape sequence used to show a desktop notification
+	#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+	#[serde(rename_all = "lowercase")]
+	pub enum TerminalNotification {
+		#[default]
+		None,
+		// iTerm2, kitty, Windows Terminal, ...
+		Osc9,
+		// urxvt, foot, VTE based terminals
+		Osc777,
+	}
+
+	/// How watcher events are announced outside of the app
+	#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+	#[serde(default)]
+	pub struct NotifyConfig {
+		// run with `sh -c` for every event, with the details in `FOURCHAN_*` environment variables
+		pub command: Option<String>,
+		pub terminal: TerminalNotification,
+	}
+
+	/// Settings of the thread watcher
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	#[serde(default)]
+	pub struct WatchConfig {
+		// how long to wait between checking all watched threads
+		pub interval_secs: u64,
+		// defaults to `default_watch_list_path()`
+		pub list: Option<PathBuf>,
+		pub notify: NotifyConfig,
+	}
+
+	impl Default for WatchConfig {
+		fn default() -> Self {
+			WatchConfig { interval_secs: 30, list: None, notify: NotifyConfig::default() }
+		}
+	}
+
+	/// Settings of the thread archiver
+	#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+	#[serde(default)]
+	pub struct ArchiveConfig {
+		// defaults to `default_archive_dir()`
+		pub dir: Option<PathBuf>,
+	}
+
+	/// How many colors images are drawn with
+	#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+	pub enum ColorDepth {
+		#[serde(rename = "truecolor")]
+		TrueColor,
+		// the 6×6×6 color cube of xterm's 256 colors
+		#[serde(rename = "256")]
+		Ansi256,
+		#[serde(rename = "16")]
+		Ansi16,
+	}
+
+	/// Settings of how things are drawn
+	#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+	#[serde(default)]
+	pub struct DisplayConfig {
+		// detected from the terminal if it isn't set
+		pub color_depth: Option<ColorDepth>,
+	}
+
+	/// Settings read from the config file. Anything missing from the file keeps it's default.
+	#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+	#[serde(default)]
+	pub struct AppConfig {
+		pub endpoints: Endpoints,
+		pub cache: CacheConfig,
+		pub watch: WatchConfig,
+		pub archive: ArchiveConfig,
+		pub display: DisplayConfig,
+	}
+
+	/// `$<var>/fourchan-tui`, falling back to `~/<fallback>/fourchan-tui` if the variable isn't set
+	fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+		env::var_os(var)
+			.filter(|dir| !dir.is_empty())
+			.map(PathBuf::from)
+			.or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(fallback)))
+			.map(|dir| dir.join("fourchan-tui"))
+	}
+
+	/// The directory our config files live in: `$XDG_CONFIG_HOME/fourchan-tui`, falling back to `~/.config/fourchan-tui`
+	pub fn config_dir() -> Option<PathBuf> {
+		xdg_dir("XDG_CONFIG_HOME", ".config")
+	}
+
+	/// The directory cached responses are stored in: `$XDG_CACHE_HOME/fourchan-tui`, falling back to `~/.cache/fourchan-tui`
+	pub fn cache_dir() -> Option<PathBuf> {
+		xdg_dir("XDG_CACHE_HOME", ".cache")
+	}
+
+	/// The directory archived threads are saved to by default: `$XDG_DATA_HOME/fourchan-tui/archive`,
+	/// falling back to `~/.local/share/fourchan-tui/archive`
+	pub fn default_archive_dir() -> Option<PathBuf> {
+		xdg_dir("XDG_DATA_HOME", ".local/share").map(|dir| dir.join("archive"))
+	}
+
+	/// Loads the app config from `path`, or from `config.json` in the config dir if no path is given.
+	/// A missing default config file isn't an error, the defaults are used instead.
+	pub fn load_app_config(path: Option<&Path>) -> Result<AppConfig, String> {
+		let path = match path {
+			Some(path) => path.to_path_buf(),
+			None => match config_dir() {
+				Some(dir) if dir.join("config.json").exists() => dir.join("config.json"),
+				_ => return Ok(AppConfig::default()),
+			},
+		};
+		let raw = fs::read_to_string(&path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+		serde_json::from_str(&raw).map_err(|e| format!("couldn't parse {}: {}", path.display(), e))
+	}
+	/// A thread on the watch list
+	#[derive(Debug, Clone, Serialize, Deserialize)]
 	pub struct ThreadConfig {
 		pub board: String,
-		pub id: String,
+		pub id: isize,
 		pub name: String,
 		#[serde(skip, default = "get_unix_epoch")]
 		pub last_modified: DateTime<Utc>,
+		// number of the newest post that has already been reported
+		#[serde(default)]
+		pub last_seen: isize,
+		// our own posts in the thread, replies to these are reported separately
+		#[serde(default)]
+		pub you: Vec<isize>,
 	}
 
 	pub fn get_unix_epoch() -> DateTime<Utc> {
 		DateTime::from_utc(NaiveDateTime::from_timestamp(0, 0), Utc)
 	}
-	#[cfg(test)] // temporary to make the unused warning go away
+	/// Parses a watch list with one `4chan/board/47357  name` line per thread.
+	/// The link can carry the watcher's state as `4chan/board/47357?seen=47400&modified=1546275948&you=47380,47390`.
 	pub fn load_config(raw_config: String) -> Vec<ThreadConfig> {
 		let mut new_config = Vec::new();
 		for (i, line) in raw_config
@@ -24,29 +210,140 @@ use std::fmt::Debug;
 		{
 			let mut config_iter = line.trim().splitn(2, |c: char| c.is_whitespace());
 			if let Some(thread_config) = config_iter.next() {
-				let target: Vec<&str> = thread_config.split("/").collect();
-				if target.len() != 3 {
-					log::info!(
-						"Unrecognized board link structure at line {}: {}",
-						i, thread_config
-					);
-					continue;
-				}
+				let (link, params) = thread_config.split_once('?').unwrap_or((thread_config, ""));
+				let target: Vec<&str> = link.split("/").collect();
+				let id = match target.get(2).map(|id| id.parse()) {
+					Some(Ok(id)) if target.len() == 3 => id,
+					_ => {
+						log::info!(
+							"Unrecognized board link structure at line {}: {}",
+							i, thread_config
+						);
+						continue;
+					}
+				};
 				// the rest of the string is in the second iterator value (or there isn't one, in which case we default to the thread config string)
 				let name = config_iter
 					.next()
-					.unwrap_or(thread_config)
+					.unwrap_or(link)
 					.trim()
 					.to_string();
 
-				new_config.push(ThreadConfig {
+				let mut config = ThreadConfig {
 					board: target[1].to_string(),
-					id: target[2].to_string(),
+					id,
 					name,
 					// the unix epoch
 					last_modified: get_unix_epoch(),
-				});
+					last_seen: 0,
+					you: Vec::new(),
+				};
+				for param in params.split('&').filter(|p| !p.is_empty()) {
+					let parsed = match param.split_once('=') {
+						Some(("seen", seen)) => seen.parse().map(|seen| config.last_seen = seen).is_ok(),
+						Some(("modified", modified)) => modified
+							.parse()
+							.map(|secs| config.last_modified = DateTime::from_utc(NaiveDateTime::from_timestamp(secs, 0), Utc))
+							.is_ok(),
+						Some(("you", you)) => you
+							.split(',')
+							.map(str::parse)
+							.collect::<Result<Vec<_>, _>>()
+							.map(|you| config.you = you)
+							.is_ok(),
+						_ => false,
+					};
+					if !parsed {
+						log::info!("Ignoring unrecognized watch list option at line {}: {}", i, param);
+					}
+				}
+				new_config.push(config);
 			}
 		}
 		new_config
 	}
+
+	/// Formats a watch list the way `load_config` reads it
+	pub fn save_config(threads: &[ThreadConfig]) -> String {
+		let mut raw_config = String::new();
+		for thread in threads {
+			raw_config.push_str(&format!(
+				"4chan/{}/{}?seen={}&modified={}",
+				thread.board, thread.id, thread.last_seen, thread.last_modified.timestamp()
+			));
+			if !thread.you.is_empty() {
+				let you: Vec<String> = thread.you.iter().map(|no| no.to_string()).collect();
+				raw_config.push_str(&format!("&you={}", you.join(",")));
+			}
+			// names can't span lines
+			raw_config.push_str(&format!("  {}\n", thread.name.replace('\n', " ")));
+		}
+		raw_config
+	}
+
+	/// Where the watch list is kept if the config doesn't say otherwise: `watchlist.txt` in the config dir
+	pub fn default_watch_list_path() -> Option<PathBuf> {
+		config_dir().map(|dir| dir.join("watchlist.txt"))
+	}
+
+	/// Reads the watch list at `path`. A missing file is an empty watch list.
+	pub fn load_watch_list(path: &Path) -> io::Result<Vec<ThreadConfig>> {
+		match fs::read_to_string(path) {
+			Ok(raw) => Ok(load_config(raw)),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+			Err(e) => Err(e),
+		}
+	}
+
+	pub fn save_watch_list(path: &Path, threads: &[ThreadConfig]) -> io::Result<()> {
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		// write a temporary file first so a crash can't leave a truncated watch list behind.
+		// The UI and the watch daemon can save at the same time, so every write gets it's own file.
+		static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+		let tmp = path.with_extension(format!("tmp{}-{}", process::id(), TMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+		fs::write(&tmp, save_config(threads))?;
+		fs::rename(tmp, path)
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::{AppConfig, CacheConfig, ColorDepth, Endpoints, load_config, save_config};
+
+		#[test]
+		fn test_partial_app_config() {
+			let config: AppConfig = serde_json::from_str(r#"{"endpoints": {"api": "http://localhost:8080"}}"#).unwrap();
+			assert_eq!(config.endpoints.api, "http://localhost:8080");
+			assert_eq!(config.endpoints.image, Endpoints::default().image);
+
+			let config: AppConfig = serde_json::from_str(r#"{"cache": {"max_size_mb": 16}}"#).unwrap();
+			assert_eq!(config.cache, CacheConfig { max_size_mb: 16, ..CacheConfig::default() });
+
+			let config: AppConfig = serde_json::from_str(r#"{"display": {"color_depth": "256"}}"#).unwrap();
+			assert_eq!(config.display.color_depth, Some(ColorDepth::Ansi256));
+
+			let config: AppConfig = serde_json::from_str("{}").unwrap();
+			assert_eq!(config.endpoints, Endpoints::default());
+			assert_eq!(config.display.color_depth, None);
+		}
+
+		#[test]
+		fn test_watch_list_roundtrip() {
+			let threads = load_config("4chan/po/570368?seen=570371&modified=1546275948&you=570369,570370  paper planes\n4chan/g/1?bogus=1".to_string());
+			assert_eq!(threads.len(), 2);
+			assert_eq!(threads[0].last_seen, 570371);
+			assert_eq!(threads[0].last_modified.timestamp(), 1546275948);
+			assert_eq!(threads[0].you, vec![570369, 570370]);
+			assert_eq!(threads[0].name, "paper planes");
+			assert_eq!(threads[1].name, "4chan/g/1");
+			assert_eq!(threads[1].last_seen, 0);
+
+			let reloaded = load_config(save_config(&threads));
+			assert_eq!(reloaded.len(), 2);
+			for (a, b) in threads.iter().zip(reloaded.iter()) {
+				assert_eq!((&a.board, a.id, &a.name, a.last_seen, &a.you), (&b.board, b.id, &b.name, b.last_seen, &b.you));
+				assert_eq!(a.last_modified, b.last_modified);
+			}
+		}
+	}
@@ -5,6 +5,42 @@ use std::{collections::HashMap, fmt::Debug};
 pub struct Thread {
 	pub posts: Vec<Post>,
 }
+/// One page of a board's `catalog.json`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CatalogPage {
+	pub page:    usize,
+	pub threads: Vec<Post>,
+}
+
+/// The OP of a thread in the catalog, along with the page the thread is on
+#[derive(Debug, Clone)]
+pub struct CatalogThread {
+	pub page: usize,
+	pub op:   Post,
+}
+
+/// Every thread on a board, in catalog order
+#[derive(Debug, Clone)]
+pub struct Catalog {
+	pub page_count: usize,
+	pub threads:    Vec<CatalogThread>,
+}
+
+impl From<Vec<CatalogPage>> for Catalog {
+	fn from(pages: Vec<CatalogPage>) -> Self {
+		Catalog {
+			page_count: pages.iter().map(|page| page.page).max().unwrap_or(0),
+			threads: pages
+				.into_iter()
+				.flat_map(|page| {
+					let page_no = page.page;
+					page.threads.into_iter().map(move |op| CatalogThread { page: page_no, op })
+				})
+				.collect(),
+		}
+	}
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Post {
 	#[serde(flatten)]
@@ -137,7 +173,7 @@ where
 
 #[cfg(test)]
 mod tests {
-	use super::Thread;
+	use super::{Catalog, CatalogPage, Thread};
 	#[test]
 	fn test_load() {
 		let bc = crate::config::load_config(
@@ -167,6 +203,18 @@ mod tests {
 		assert!(tested.posts.get(2).unwrap().attachment.is_some());
 	}
 
+	#[test]
+	fn test_catalog_pages() {
+		let test = std::fs::read_to_string("assets/test/benchdata.json").unwrap();
+		let pages: Vec<CatalogPage> = serde_json::de::from_str(&test).unwrap();
+		let catalog = Catalog::from(pages);
+		assert!(catalog.page_count == 11);
+		assert!(catalog.threads.len() == 151);
+		assert!(catalog.threads[0].page == 1);
+		assert!(catalog.threads[15].page == 2);
+		assert!(catalog.threads[150].page == 11);
+	}
+
 	#[test]
 	fn test_board_deserialize() {
 		let test = std::fs::read_to_string("assets/test/boards.json").unwrap();
@@ -0,0 +1,96 @@
+use chrono::{TimeZone, Utc};
+
+use crate::{data::{AttachmentData, Post, Thread}, quotes::QuoteLink};
+
+pub mod html;
+pub mod markdown;
+pub mod text;
+
+/// The formats a thread can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	Html,
+	Markdown,
+	Text,
+}
+
+impl Format {
+	pub fn extension(self) -> &'static str {
+		match self {
+			Format::Html => "html",
+			Format::Markdown => "md",
+			Format::Text => "txt",
+		}
+	}
+}
+
+/// Exports a thread in the given format
+pub fn export(format: Format, board: &str, thread: &Thread) -> String {
+	match format {
+		Format::Html => html::thread_to_html(board, thread),
+		Format::Markdown => markdown::thread_to_markdown(board, thread),
+		Format::Text => text::thread_to_text(board, thread),
+	}
+}
+
+/// When a post was made, in UTC so exports look the same wherever they're made
+fn format_time(post: &Post) -> String {
+	Utc.timestamp(post.time as i64, 0).format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+/// The subject of the thread, or it's number if it doesn't have one
+fn thread_title(thread: &Thread) -> String {
+	let op = thread.posts.first();
+	op.and_then(|op| op.op_data.as_ref())
+		.and_then(|op_data| op_data.sub.clone())
+		.unwrap_or_else(|| format!("No.{}", op.map_or(0, |op| op.no)))
+}
+
+/// Link to the thread on 4chan
+fn thread_url(board: &str, thread: &Thread) -> String {
+	format!("https://boards.4chan.org/{}/thread/{}", board, thread.posts.first().map_or(0, |op| op.no))
+}
+
+/// `name.ext (123 KB, 500x400)`, like the file info above attachments on the site
+fn file_info(attachment: &AttachmentData) -> String {
+	format!(
+		"{}{} ({} KB, {}x{})",
+		attachment.filename, attachment.ext, attachment.fsize / 1024, attachment.w, attachment.h
+	)
+}
+
+/// Where the archiver saves the full-size attachment, relative to the thread's directory
+fn local_image(attachment: &AttachmentData) -> String {
+	format!("images/{}{}", attachment.tim, attachment.ext)
+}
+
+/// Where the archiver saves the thumbnail, relative to the thread's directory
+fn local_thumbnail(attachment: &AttachmentData) -> String {
+	format!("thumbs/{}s.jpg", attachment.tim)
+}
+
+/// Where a quotelink should go in an export: an anchor for posts in this thread, 4chan for everything else
+fn quotelink_target(href: &str, board: &str, thread: isize) -> String {
+	match QuoteLink::from_href(href, board, thread) {
+		Some(QuoteLink::Post(post)) => format!("#p{}", post),
+		Some(QuoteLink::CrossThread { board, thread, post }) => format!("https://boards.4chan.org/{}/thread/{}#p{}", board, thread, post),
+		Some(QuoteLink::Board { board, search: Some(search) }) => format!("https://boards.4chan.org/{}/catalog#s={}", board, search),
+		Some(QuoteLink::Board { board, search: None }) => format!("https://boards.4chan.org/{}/", board),
+		None => href.to_string(),
+	}
+}
+
+/// The poster's name, tripcode, ID and capcode, separated by spaces
+fn poster(post: &Post) -> String {
+	let mut poster = post.name.clone().unwrap_or_else(|| "Anonymous".to_string());
+	if let Some(trip) = &post.trip {
+		poster.push_str(&format!(" {}", trip));
+	}
+	if let Some(id) = &post.id {
+		poster.push_str(&format!(" (ID: {})", id));
+	}
+	if let Some(capcode) = &post.capcode {
+		poster.push_str(&format!(" ## {}", capcode));
+	}
+	poster
+}
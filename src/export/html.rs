@@ -0,0 +1,221 @@
+use crate::{data::{Post, Thread}, html::{self, Tag, Token}, quotes::ThreadIndex};
+
+use super::{file_info, format_time, local_image, local_thumbnail, quotelink_target, thread_title};
+
+const STYLE: &str = "\
+body { background: #eef2ff; color: #000; font: 13px arial, helvetica, sans-serif; margin: 1em; }
+h1 { color: #af0a0f; font-size: 24px; text-align: center; }
+.post { background: #d6daf0; border: 1px solid #b7c5d9; margin: 4px 0; padding: 4px 8px; display: table; }
+.post.op { background: none; border: none; display: block; }
+.name { color: #117743; font-weight: bold; }
+.trip { color: #117743; }
+.capcode { color: #f00; font-weight: bold; }
+.file { float: left; margin: 3px 20px 5px 0; }
+.file-info { font-size: 11px; }
+blockquote { margin: 1em 2em; overflow-wrap: anywhere; }
+.quote { color: #789922; }
+.quotelink { color: #d00; }
+.deadlink { color: #d00; text-decoration: line-through; }
+.backlinks { font-size: 11px; clear: both; }
+s { background: #000; color: #000; text-decoration: none; }
+s:hover { color: #fff; }
+pre { background: #fff; border: 1px solid #b7c5d9; padding: 4px; }";
+
+/// Escapes text for use in HTML, including inside of attribute values
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&#39;"),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+/// Whether a link can go into the page as it is: web links and relative ones, but nothing like `javascript:`
+fn is_safe_href(href: &str) -> bool {
+	let lower = href.trim_start().to_ascii_lowercase();
+	// a scheme is everything before the first `:`, as long as that comes before any path, query or fragment
+	match lower.find([':', '/', '?', '#']) {
+		Some(i) if lower[i..].starts_with(':') => lower.starts_with("http:") || lower.starts_with("https:"),
+		_ => true,
+	}
+}
+
+/// Rebuilds a comment from it's tokens, so only markup we know about ends up in the page
+fn comment_to_html(com: &str, board: &str, thread: isize) -> String {
+	let mut out = String::new();
+	// name of every open tag in the comment, and what it was turned into
+	let mut open: Vec<(String, &str)> = Vec::new();
+	for token in html::tokenize(com) {
+		match token {
+			Token::Text(text) => out.push_str(&escape(&text)),
+			Token::LineBreak => out.push_str("<br>"),
+			Token::Open { name, tag } => {
+				let (opening, closing) = match tag {
+					Tag::Bold => ("<b>".to_string(), "</b>"),
+					Tag::Italic => ("<i>".to_string(), "</i>"),
+					Tag::Underline => ("<u>".to_string(), "</u>"),
+					Tag::Spoiler => ("<s>".to_string(), "</s>"),
+					Tag::Greentext => ("<span class=\"quote\">".to_string(), "</span>"),
+					Tag::QuoteLink(href) => match quotelink_target(&href, board, thread) {
+						target if is_safe_href(&target) => (format!("<a class=\"quotelink\" href=\"{}\">", escape(&target)), "</a>"),
+						// hrefs we can't make sense of are passed through, so they need the same check as other links
+						_ => ("<span class=\"quotelink\">".to_string(), "</span>"),
+					},
+					Tag::Link(href) if is_safe_href(&href) => (format!("<a href=\"{}\">", escape(&href)), "</a>"),
+					// the text is kept, without a link to follow
+					Tag::Link(_) => ("<span>".to_string(), "</span>"),
+					Tag::DeadLink => ("<span class=\"deadlink\">".to_string(), "</span>"),
+					Tag::Code => ("<pre>".to_string(), "</pre>"),
+					Tag::Plain => ("<span>".to_string(), "</span>"),
+				};
+				out.push_str(&opening);
+				open.push((name, closing));
+			}
+			Token::Close { name } => {
+				// same as the terminal view, unbalanced closing tags are ignored
+				if let Some(i) = open.iter().rposition(|(n, _)| *n == name) {
+					for (_, closing) in open.drain(i..).rev() {
+						out.push_str(closing);
+					}
+				}
+			}
+		}
+	}
+	for (_, closing) in open.into_iter().rev() {
+		out.push_str(closing);
+	}
+	out
+}
+
+fn post_to_html(post: &Post, board: &str, thread: isize, index: &ThreadIndex) -> String {
+	let mut out = format!("<div class=\"post{}\" id=\"p{}\">\n", if post.resto == 0 { " op" } else { "" }, post.no);
+
+	out.push_str("<div class=\"header\">");
+	if let Some(sub) = post.op_data.as_ref().and_then(|op_data| op_data.sub.as_ref()) {
+		out.push_str(&format!("<b>{}</b> ", escape(sub)));
+	}
+	out.push_str(&format!("<span class=\"name\">{}</span>", escape(post.name.as_deref().unwrap_or("Anonymous"))));
+	if let Some(trip) = &post.trip {
+		out.push_str(&format!(" <span class=\"trip\">{}</span>", escape(trip)));
+	}
+	if let Some(id) = &post.id {
+		out.push_str(&format!(" (ID: {})", escape(id)));
+	}
+	if let Some(capcode) = &post.capcode {
+		out.push_str(&format!(" <span class=\"capcode\">## {}</span>", escape(capcode)));
+	}
+	out.push_str(&format!(" {} <a href=\"#p{1}\">No.{1}</a></div>\n", format_time(post), post.no));
+
+	if let Some(attachment) = &post.attachment {
+		out.push_str("<div class=\"file\">");
+		if attachment.filedeleted {
+			out.push_str("File deleted.");
+		} else {
+			out.push_str(&format!(
+				"<div class=\"file-info\"><a href=\"{0}\">{1}</a></div><a href=\"{0}\"><img src=\"{2}\" width=\"{3}\" height=\"{4}\" alt=\"{1}\" loading=\"lazy\"></a>",
+				escape(&local_image(attachment)), escape(&file_info(attachment)), escape(&local_thumbnail(attachment)), attachment.tn_w, attachment.tn_h
+			));
+		}
+		out.push_str("</div>\n");
+	}
+
+	if let Some(com) = &post.com {
+		out.push_str(&format!("<blockquote>{}</blockquote>\n", comment_to_html(com, board, thread)));
+	}
+
+	let replies = index.replies(post.no);
+	if !replies.is_empty() {
+		out.push_str("<div class=\"backlinks\">Replies:");
+		for reply in replies {
+			out.push_str(&format!(" <a class=\"quotelink\" href=\"#p{0}\">&gt;&gt;{0}</a>", reply));
+		}
+		out.push_str("</div>\n");
+	}
+	out.push_str("</div>\n");
+	out
+}
+
+/// Renders a thread as a standalone HTML page. Quotelinks within the thread point to anchors on the page,
+/// and images are linked at the paths the archiver saves them to, so the page belongs in the thread's archive directory.
+pub fn thread_to_html(board: &str, thread: &Thread) -> String {
+	let thread_no = thread.posts.first().map_or(0, |op| op.no);
+	let index = ThreadIndex::new(board, thread);
+	let title = format!("/{}/ - {}", board, thread_title(thread));
+	let mut out = format!(
+		"<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>\n{1}\n</style>\n</head>\n<body>\n<h1>{0}</h1>\n",
+		escape(&title), STYLE
+	);
+	for post in thread.posts.iter() {
+		out.push_str(&post_to_html(post, board, thread_no, &index));
+	}
+	out.push_str("</body>\n</html>\n");
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{comment_to_html, thread_to_html};
+	use crate::data::Thread;
+
+	fn load_dummy() -> Thread {
+		let test = std::fs::read_to_string("assets/test/dummy.json").unwrap();
+		serde_json::de::from_str(&test).unwrap()
+	}
+
+	#[test]
+	fn test_dummy_page() {
+		let page = thread_to_html("po", &load_dummy());
+		assert!(page.starts_with("<!DOCTYPE html>"));
+		assert!(page.contains("<title>/po/ - Welcome to /po/!</title>"));
+		for no in [570368, 570370, 570371] {
+			assert!(page.contains(&format!("id=\"p{}\"", no)));
+		}
+		assert!(page.contains("<div class=\"post op\" id=\"p570368\">"));
+		assert!(page.contains("<img src=\"thumbs/1546293948883s.jpg\" width=\"250\" height=\"211\" alt=\"yotsuba_folding.png (504 KB, 530x449)\""));
+		assert!(page.contains("<a href=\"images/1546294889019.jpg\">"));
+		assert!(page.contains("<span class=\"capcode\">## mod</span> 2018-12-31 22:05:48 UTC"));
+		assert!(page.contains("<b>FAQs about papercraft</b><br><br><i>What paper should I use?</i>"));
+		// the cross-board link in the last post points back to 4chan
+		assert!(page.contains("<a class=\"quotelink\" href=\"https://boards.4chan.org/po/catalog#s=lft\">&gt;&gt;&gt;/po/lft</a>"));
+		assert!(page.contains("Pepakura Designer is a program that takes 3D models and `unfolds&#39; them"));
+		assert!(!page.contains("<div class=\"backlinks\">"));
+	}
+
+	#[test]
+	fn test_quotelinks_and_backlinks() {
+		let mut thread = load_dummy();
+		thread.posts[1].com = Some("<a href=\"#p570368\" class=\"quotelink\">&gt;&gt;570368</a><br><span class=\"quote\">&gt;be me</span>".into());
+		thread.posts[2].com = Some("<a href=\"/po/thread/570368#p570370\" class=\"quotelink\">&gt;&gt;570370</a> <script>alert(1)</script>".into());
+		let page = thread_to_html("po", &thread);
+		assert!(page.contains("<blockquote><a class=\"quotelink\" href=\"#p570368\">&gt;&gt;570368</a><br><span class=\"quote\">&gt;be me</span></blockquote>"));
+		assert!(page.contains("<a class=\"quotelink\" href=\"#p570370\">&gt;&gt;570370</a>"));
+		assert!(page.contains("<div class=\"backlinks\">Replies: <a class=\"quotelink\" href=\"#p570370\">&gt;&gt;570370</a></div>"));
+		assert!(page.contains("<div class=\"backlinks\">Replies: <a class=\"quotelink\" href=\"#p570371\">&gt;&gt;570371</a></div>"));
+		assert!(!page.contains("<script>"));
+	}
+
+	#[test]
+	fn test_link_schemes() {
+		assert_eq!(comment_to_html("<a href=\"https://example.com/a?b\">ok</a>", "po", 1), "<a href=\"https://example.com/a?b\">ok</a>");
+		assert_eq!(comment_to_html("<a href=\"/po/catalog\">ok</a>", "po", 1), "<a href=\"/po/catalog\">ok</a>");
+		assert_eq!(comment_to_html("<a href=\"JavaScript:alert(1)\">x</a>", "po", 1), "<span>x</span>");
+		assert_eq!(comment_to_html("<a href=\" data:text/html,x\">x</a>", "po", 1), "<span>x</span>");
+		assert_eq!(
+			comment_to_html("<a class=\"quotelink\" href=\"javascript://%0aalert(1)\">&gt;&gt;1</a>", "po", 1),
+			"<span class=\"quotelink\">&gt;&gt;1</span>"
+		);
+	}
+
+	#[test]
+	fn test_unbalanced_tags() {
+		assert_eq!(comment_to_html("<b>bold <i>both</b> none</i>", "po", 1), "<b>bold <i>both</i></b> none");
+		assert_eq!(comment_to_html("<s>spoiler", "po", 1), "<s>spoiler</s>");
+	}
+}
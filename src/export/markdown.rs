@@ -0,0 +1,210 @@
+use crate::{data::{Post, Thread}, html::{self, Tag, Token}, net, quotes::ThreadIndex};
+
+use super::{file_info, format_time, poster, quotelink_target, thread_title, thread_url};
+
+/// Escapes everything Markdown could mistake for formatting
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		if "\\`*_[]<>#|~!".contains(c) {
+			escaped.push('\\');
+		}
+		escaped.push(c);
+	}
+	escaped
+}
+
+/// One line of a comment, whether it's greentext, and whether it starts inside a code block
+struct Line {
+	greentext: bool,
+	code:      bool,
+	text:      String,
+}
+
+/// A link destination in angle brackets can hold spaces and parentheses, only the brackets themselves need encoding
+fn link_destination(href: &str) -> String {
+	format!("<{}>", href.replace('<', "%3C").replace('>', "%3E"))
+}
+
+/// Converts a comment into Markdown. Greentext lines become blockquotes, and quotelinks link to
+/// the post's anchor (or to 4chan if they leave the thread).
+fn comment_to_markdown(com: &str, board: &str, thread: isize) -> String {
+	let mut lines = vec![Line { greentext: false, code: false, text: String::new() }];
+	// the Markdown each open tag has to be closed with, and whether it's a code block
+	let mut open: Vec<(String, String, bool)> = Vec::new();
+	let mut in_code = false;
+	for token in html::tokenize(com) {
+		let line = lines.last_mut().unwrap();
+		match token {
+			Token::Text(text) => {
+				if in_code {
+					line.text.push_str(&text);
+				} else if line.greentext && line.text.is_empty() {
+					// the blockquote already shows the `>`
+					line.text.push_str(&escape(text.strip_prefix('>').unwrap_or(&text)));
+				} else {
+					line.text.push_str(&escape(&text));
+				}
+			}
+			Token::LineBreak => lines.push(Line { greentext: false, code: in_code, text: String::new() }),
+			Token::Open { name, tag } => {
+				let is_code = tag == Tag::Code;
+				let (opening, closing) = match tag {
+					Tag::Bold => ("**".to_string(), "**".to_string()),
+					Tag::Italic => ("*".to_string(), "*".to_string()),
+					Tag::Greentext if line.text.is_empty() => {
+						line.greentext = true;
+						(String::new(), String::new())
+					}
+					Tag::QuoteLink(href) => ("[".to_string(), format!("]({})", link_destination(&quotelink_target(&href, board, thread)))),
+					Tag::Link(href) => ("[".to_string(), format!("]({})", link_destination(&href))),
+					Tag::DeadLink => ("~~".to_string(), "~~".to_string()),
+					Tag::Code => {
+						in_code = true;
+						("\n```\n".to_string(), "\n```\n".to_string())
+					}
+					// no Markdown for underlines and spoilers
+					_ => (String::new(), String::new()),
+				};
+				line.text.push_str(&opening);
+				open.push((name, closing, is_code));
+			}
+			Token::Close { name } => {
+				if let Some(i) = open.iter().rposition(|(n, _, _)| *n == name) {
+					for (_, closing, is_code) in open.drain(i..).rev() {
+						line.text.push_str(&closing);
+						if is_code {
+							in_code = false;
+						}
+					}
+				}
+			}
+		}
+	}
+	let line = lines.last_mut().unwrap();
+	for (_, closing, _) in open.into_iter().rev() {
+		line.text.push_str(&closing);
+	}
+
+	let mut out = String::new();
+	for (i, line) in lines.iter().enumerate() {
+		if line.text.is_empty() {
+			out.push('\n');
+			continue;
+		}
+		if line.greentext {
+			out.push_str("> ");
+		}
+		out.push_str(&line.text);
+		match lines.get(i + 1) {
+			// lines of a code block are kept as they are, and one starting the block is already set apart by the fence
+			Some(next) if next.code || next.text.starts_with("\n```") => out.push('\n'),
+			// a backslash keeps the next line in the same paragraph (or blockquote)
+			Some(next) if !next.text.is_empty() && next.greentext == line.greentext => out.push_str("\\\n"),
+			// anything else needs a blank line, or the next line would continue the blockquote
+			Some(next) if !next.text.is_empty() => out.push_str("\n\n"),
+			_ => out.push('\n'),
+		}
+	}
+	out
+}
+
+fn post_to_markdown(post: &Post, board: &str, thread: isize, index: &ThreadIndex) -> String {
+	let mut out = format!(
+		"---\n\n<a id=\"p{0}\"></a>\n**{1}** {2} No.{0}\n\n",
+		post.no, escape(&poster(post)), format_time(post)
+	);
+	if let Some(attachment) = &post.attachment {
+		if attachment.filedeleted {
+			out.push_str("*File deleted.*\n\n");
+		} else {
+			out.push_str(&format!(
+				"[{}]({})\n\n",
+				escape(&file_info(attachment)), net::image_url(board, attachment.tim, &attachment.ext)
+			));
+		}
+	}
+	if let Some(com) = &post.com {
+		out.push_str(&comment_to_markdown(com, board, thread));
+		out.push('\n');
+	}
+	let replies = index.replies(post.no);
+	if !replies.is_empty() {
+		let links: Vec<String> = replies.iter().map(|reply| format!("[\\>\\>{0}](#p{0})", reply)).collect();
+		out.push_str(&format!("Replies: {}\n\n", links.join(" ")));
+	}
+	out
+}
+
+/// Renders a thread as Markdown, with a header for each post and links to every image on 4chan
+pub fn thread_to_markdown(board: &str, thread: &Thread) -> String {
+	let thread_no = thread.posts.first().map_or(0, |op| op.no);
+	let index = ThreadIndex::new(board, thread);
+	let mut out = format!("# /{}/ - {}\n\n<{}>\n\n", board, escape(&thread_title(thread)), thread_url(board, thread));
+	for post in thread.posts.iter() {
+		out.push_str(&post_to_markdown(post, board, thread_no, &index));
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{comment_to_markdown, thread_to_markdown};
+	use crate::data::Thread;
+
+	fn load_dummy() -> Thread {
+		let test = std::fs::read_to_string("assets/test/dummy.json").unwrap();
+		serde_json::de::from_str(&test).unwrap()
+	}
+
+	#[test]
+	fn test_greentext_and_quotelinks() {
+		let com = "<a href=\"#p570368\" class=\"quotelink\">&gt;&gt;570368</a><br>\
+			<span class=\"quote\">&gt;be me</span><br><span class=\"quote\">&gt;fold *paper*</span><br>\
+			text after<br><br><b>bold</b> <a href=\"/g/thread/1#p2\" class=\"quotelink\">&gt;&gt;&gt;/g/2</a>";
+		assert_eq!(
+			comment_to_markdown(com, "po", 570368),
+			"[\\>\\>570368](<#p570368>)\n\n\
+			> be me\\\n\
+			> fold \\*paper\\*\n\n\
+			text after\n\
+			\n\
+			**bold** [\\>\\>\\>/g/2](<https://boards.4chan.org/g/thread/1#p2>)\n"
+		);
+	}
+
+	#[test]
+	fn test_code_blocks_and_links() {
+		let com = "code:<br><pre class=\"prettyprint\">fn main() {<br>    <span class=\"x\">*x*</span> = 1;<br>}</pre>\
+			after<br><a href=\"https://en.wikipedia.org/wiki/Fold_(origami) x\">wiki</a>";
+		assert_eq!(
+			comment_to_markdown(com, "po", 570368),
+			"code:\n\
+			\n```\nfn main() {\n    *x* = 1;\n\
+			}\n```\nafter\\\n\
+			[wiki](<https://en.wikipedia.org/wiki/Fold_(origami) x>)\n"
+		);
+	}
+
+	#[test]
+	fn test_quotelink_search() {
+		let com = "<a href=\"//boards.4channel.org/po/catalog#s=paper crane)\" class=\"quotelink\">&gt;&gt;&gt;/po/paper crane)</a>";
+		assert_eq!(
+			comment_to_markdown(com, "po", 570368),
+			"[\\>\\>\\>/po/paper crane)](<https://boards.4chan.org/po/catalog#s=paper crane)>)\n"
+		);
+	}
+
+	#[test]
+	fn test_dummy_markdown() {
+		let mut thread = load_dummy();
+		thread.posts[1].com = Some("<a href=\"#p570368\" class=\"quotelink\">&gt;&gt;570368</a> hi".into());
+		let markdown = thread_to_markdown("po", &thread);
+		assert!(markdown.starts_with("# /po/ - Welcome to /po/\\!\n\n<https://boards.4chan.org/po/thread/570368>\n\n"));
+		assert!(markdown.contains("<a id=\"p570368\"></a>\n**Anonymous \\#\\# mod** 2018-12-31 22:05:48 UTC No.570368\n\n"));
+		assert!(markdown.contains("[yotsuba\\_folding.png (504 KB, 530x449)](https://i.4cdn.org/po/1546293948883.png)"));
+		assert!(markdown.contains("Replies: [\\>\\>570370](#p570370)"));
+		assert!(markdown.contains("[\\>\\>570368](<#p570368>) hi"));
+		assert!(markdown.contains("**FAQs about origami**"));
+	}
+}
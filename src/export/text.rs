@@ -0,0 +1,54 @@
+use crate::{data::{Post, Thread}, html, net, quotes::ThreadIndex};
+
+use super::{file_info, format_time, poster, thread_title, thread_url};
+
+fn post_to_text(post: &Post, board: &str, index: &ThreadIndex) -> String {
+	let mut out = format!("{} {} No.{}\n", poster(post), format_time(post), post.no);
+	if let Some(attachment) = &post.attachment {
+		if attachment.filedeleted {
+			out.push_str("File deleted.\n");
+		} else {
+			out.push_str(&format!("File: {} {}\n", file_info(attachment), net::image_url(board, attachment.tim, &attachment.ext)));
+		}
+	}
+	if let Some(com) = &post.com {
+		out.push('\n');
+		out.push_str(html::comment_to_text(com).trim_end());
+		out.push('\n');
+	}
+	let replies = index.replies(post.no);
+	if !replies.is_empty() {
+		let links: Vec<String> = replies.iter().map(|reply| format!(">>{}", reply)).collect();
+		out.push_str(&format!("\nReplies: {}\n", links.join(" ")));
+	}
+	out
+}
+
+/// Renders a thread as plain text, the way it reads on the site
+pub fn thread_to_text(board: &str, thread: &Thread) -> String {
+	let index = ThreadIndex::new(board, thread);
+	let mut out = format!("/{}/ - {}\n{}\n", board, thread_title(thread), thread_url(board, thread));
+	for post in thread.posts.iter() {
+		out.push_str(&format!("\n{}\n", "-".repeat(40)));
+		out.push_str(&post_to_text(post, board, &index));
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::thread_to_text;
+	use crate::data::Thread;
+
+	#[test]
+	fn test_dummy_text() {
+		let test = std::fs::read_to_string("assets/test/dummy.json").unwrap();
+		let mut thread: Thread = serde_json::de::from_str(&test).unwrap();
+		thread.posts[2].com = Some("<a href=\"#p570370\" class=\"quotelink\">&gt;&gt;570370</a><br><span class=\"quote\">&gt;be me</span>".into());
+		let text = thread_to_text("po", &thread);
+		assert!(text.starts_with("/po/ - Welcome to /po/!\nhttps://boards.4chan.org/po/thread/570368\n\n----"));
+		assert!(text.contains("Anonymous ## mod 2018-12-31 22:05:48 UTC No.570368\nFile: yotsuba_folding.png (504 KB, 530x449) https://i.4cdn.org/po/1546293948883.png\n\nWelcome to /po/!"));
+		assert!(text.contains("\nReplies: >>570371\n"));
+		assert!(text.ends_with("No.570371\nFile: origami faq.jpg (159 KB, 762x762) https://i.4cdn.org/po/1546294889019.jpg\n\n>>570370\n>be me\n"));
+	}
+}
@@ -0,0 +1,134 @@
+use std::{fs, io::{self, Write}, thread::{self, JoinHandle}, time::Duration};
+
+use crate::{archive, cli::{ArchiveArgs, ExportArgs, OutputFormat, WatchArgs}, config::{self, AppConfig, ThreadConfig}, export, net, notify::Notifier, watcher::{ThreadStatus, Watcher}};
+
+/// Runs the thread watcher without the UI, printing every event to stdout.
+/// Returns once every thread is dead (or after one check with `--once`), and with an error if
+/// the watch list can't be read or saved, stdout is gone, or a single check couldn't reach any thread.
+pub fn run_watch(args: WatchArgs, app_config: &AppConfig) -> Result<(), String> {
+	// threads from the command line are watched as is, the watch list is also saved again
+	let (threads, list_path) = if args.threads.is_empty() {
+		let path = app_config.watch.list.clone()
+			.or_else(config::default_watch_list_path)
+			.ok_or("couldn't find the watch list, set `watch.list` in the config")?;
+		let threads = config::load_watch_list(&path)
+			.map_err(|e| format!("couldn't read the watch list {}: {}", path.display(), e))?;
+		(threads, Some(path))
+	} else {
+		let threads = args.threads
+			.into_iter()
+			.map(|(board, id)| ThreadConfig {
+				name: format!("/{}/{}", board, id),
+				board,
+				id,
+				last_modified: config::get_unix_epoch(),
+				last_seen: 0,
+				you: Vec::new(),
+			})
+			.collect();
+		(threads, None)
+	};
+	if threads.is_empty() {
+		return Err("there are no threads to watch, add some from the UI or pass them like g/12345".to_string());
+	}
+
+	let watcher = Watcher::new(threads);
+	let notifier = Notifier::new(app_config.watch.notify.clone());
+	let interval = Duration::from_secs(args.interval.unwrap_or(app_config.watch.interval_secs));
+	let format = args.format;
+	let stdout = io::stdout();
+	// hooks that may still be running, waited for before exiting so they aren't cut off
+	let mut hooks = Vec::new();
+	loop {
+		hooks.retain(|hook: &JoinHandle<()>| !hook.is_finished());
+		let mut write_error = None;
+		let result = {
+			let mut out = stdout.lock();
+			watcher.poll(&mut |event| {
+				if write_error.is_some() {
+					return;
+				}
+				let result = match format {
+					OutputFormat::Lines => match event.excerpt(80) {
+						Some(excerpt) => writeln!(out, "{}: {}", event, excerpt),
+						None => writeln!(out, "{}", event),
+					},
+					OutputFormat::Json => writeln!(out, "{}", event.to_json()),
+				};
+				// flush every line so pipes see events as they happen
+				write_error = result.and_then(|_| out.flush()).err();
+				// terminal notifications go to stderr to keep them out of the output
+				hooks.extend(notifier.notify(&event, &mut io::stderr()));
+			})
+		};
+		if let Some(e) = write_error {
+			return Err(format!("couldn't write to stdout: {}", e));
+		}
+		match result {
+			// a single check that can't reach the API at all has nothing to show for itself
+			Err(e) if args.once => return Err(format!("couldn't check any of the watched threads: {}", e)),
+			Err(e) => eprintln!("Couldn't check any of the watched threads, trying again later: {}", e),
+			Ok(()) => {}
+		}
+		if let Some(path) = &list_path {
+			config::save_watch_list(path, &watcher.configs())
+				.map_err(|e| format!("couldn't save the watch list to {}: {}", path.display(), e))?;
+		}
+		let done = args.once || watcher.threads().iter().all(|watched| watched.status != ThreadStatus::Alive);
+		if done {
+			if !args.once {
+				eprintln!("None of the watched threads can get new posts anymore");
+			}
+			for hook in hooks {
+				let _ = hook.join();
+			}
+			return Ok(());
+		}
+		thread::sleep(interval);
+	}
+}
+
+/// Archives every thread in `args`, printing what was done for each of them.
+/// Fails if any thread or file couldn't be archived, after trying all of them.
+pub fn run_archive(args: ArchiveArgs, app_config: &AppConfig) -> Result<(), String> {
+	let root = args.dir
+		.or_else(|| app_config.archive.dir.clone())
+		.or_else(config::default_archive_dir)
+		.ok_or("couldn't find a directory to archive to, pass one with --dir")?;
+	let mut failures = 0;
+	for (board, no) in args.threads {
+		match archive::archive_thread(&root, &board, no) {
+			Ok(report) => {
+				println!(
+					"/{}/{}: {} new posts, {} files downloaded, {} already archived -> {}",
+					board, no, report.new_posts, report.downloaded, report.skipped,
+					archive::thread_dir(&root, &board, no).display()
+				);
+				for (file, e) in report.failed.iter() {
+					eprintln!("/{}/{}: couldn't archive {}: {}", board, no, file, e);
+				}
+				failures += report.failed.len();
+			}
+			Err(e) => {
+				eprintln!("/{}/{}: {}", board, no, e);
+				failures += 1;
+			}
+		}
+	}
+	match failures {
+		0 => Ok(()),
+		n => Err(format!("{} thread(s) or file(s) couldn't be archived", n)),
+	}
+}
+
+/// Exports a thread to stdout, or to the output file if one was given
+pub fn run_export(args: ExportArgs) -> Result<(), String> {
+	let (board, no) = args.thread.ok_or("no thread to export")?;
+	let thread = net::get_thread(&board, no, net::Priority::User)
+		.map_err(|e| format!("couldn't load /{}/{}: {}", board, no, e))?;
+	let exported = export::export(args.format, &board, &thread);
+	match args.output {
+		Some(path) => fs::write(&path, exported).map_err(|e| format!("couldn't write {}: {}", path.display(), e)),
+		None => io::stdout().write_all(exported.as_bytes()).map_err(|e| format!("couldn't write to stdout: {}", e)),
+	}
+}
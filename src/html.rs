@@ -0,0 +1,378 @@
+use std::ops::Range;
+
+use cursive::{theme::{BaseColor, Color, Effect, Style}, utils::markup::StyledString};
+
+/// A single piece of a post comment, as produced by `tokenize`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+	// text with all of it's entities already decoded
+	Text(String),
+	LineBreak,
+	Open { name: String, tag: Tag },
+	Close { name: String },
+}
+
+/// The meaning of an opening tag, as far as rendering a comment is concerned
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+	Bold,
+	Italic,
+	Underline,
+	// 4chan uses <s> for spoilers, not strikethrough
+	Spoiler,
+	// <span class="quote">, a line starting with ">"
+	Greentext,
+	// <a class="quotelink">, links to another post; contains the href
+	QuoteLink(String),
+	// any other <a>; contains the href
+	Link(String),
+	// <span class="deadlink">, a quote of a post that no longer exists
+	DeadLink,
+	// <pre class="prettyprint"> on boards with code tags
+	Code,
+	// any tag we don't give special treatment
+	Plain,
+}
+
+impl Tag {
+	fn from_element(name: &str, class: Option<&str>, href: Option<&str>) -> Tag {
+		let has_class = |wanted: &str| class.is_some_and(|c| c.split_whitespace().any(|c| c == wanted));
+		match name {
+			"b" | "strong" => Tag::Bold,
+			"i" | "em" => Tag::Italic,
+			"u" => Tag::Underline,
+			"s" => Tag::Spoiler,
+			"pre" | "code" => Tag::Code,
+			"span" if has_class("quote") => Tag::Greentext,
+			"span" if has_class("deadlink") => Tag::DeadLink,
+			"a" if has_class("quotelink") => Tag::QuoteLink(href.unwrap_or_default().to_string()),
+			"a" => Tag::Link(href.unwrap_or_default().to_string()),
+			_ => Tag::Plain,
+		}
+	}
+
+	fn style(&self) -> Style {
+		match self {
+			Tag::Bold       => Effect::Bold.into(),
+			Tag::Italic     => Effect::Italic.into(),
+			Tag::Underline  => Effect::Underline.into(),
+			Tag::Spoiler    => Effect::Reverse.into(),
+			Tag::Greentext  => Color::Light(BaseColor::Green).into(),
+			Tag::QuoteLink(_) => Style::from(Color::Light(BaseColor::Red)).combine(Effect::Underline),
+			Tag::Link(_)    => Effect::Underline.into(),
+			Tag::DeadLink   => Style::from(Color::Dark(BaseColor::Red)).combine(Effect::Strikethrough),
+			Tag::Code       => Color::Light(BaseColor::Cyan).into(),
+			Tag::Plain      => Style::none(),
+		}
+	}
+}
+
+/// Splits a 4chan comment into text, line breaks and tags.
+/// `<wbr>` tags and HTML comments are dropped entirely.
+pub fn tokenize(html: &str) -> Vec<Token> {
+	let mut tokens = Vec::new();
+	let mut rest = html;
+	while !rest.is_empty() {
+		let tag_start = match rest.find('<') {
+			Some(i) => i,
+			None => {
+				push_text(&mut tokens, rest);
+				break;
+			}
+		};
+		push_text(&mut tokens, &rest[..tag_start]);
+		rest = &rest[tag_start..];
+
+		// a '<' that doesn't start a tag is just text (4chan escapes these, but be lenient)
+		let starts_tag = rest[1..].chars().next()
+			.is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!');
+		let tag_end = match rest.find('>') {
+			Some(i) if starts_tag => i,
+			_ => {
+				push_text(&mut tokens, "<");
+				rest = &rest[1..];
+				continue;
+			}
+		};
+		if rest.starts_with("<!--") {
+			rest = match rest.find("-->") {
+				Some(end) => &rest[end + 3..],
+				None => "",
+			};
+			continue;
+		}
+		if let Some(token) = parse_tag(&rest[1..tag_end]) {
+			tokens.push(token);
+		}
+		rest = &rest[tag_end + 1..];
+	}
+	tokens
+}
+
+fn push_text(tokens: &mut Vec<Token>, raw: &str) {
+	// 4chan marks line breaks with <br>, any raw newlines are just formatting noise around the tags
+	let text = raw.trim_matches('\n').replace('\n', " ");
+	if text.is_empty() {
+		return;
+	}
+	let text = decode_entities(&text);
+	// merge with the previous text token so entities and dropped <wbr>s don't split words up
+	if let Some(Token::Text(prev)) = tokens.last_mut() {
+		prev.push_str(&text);
+	} else {
+		tokens.push(Token::Text(text));
+	}
+}
+
+fn parse_tag(inner: &str) -> Option<Token> {
+	let inner = inner.trim().trim_end_matches('/');
+	if let Some(name) = inner.strip_prefix('/') {
+		return Some(Token::Close { name: name.trim().to_ascii_lowercase() });
+	}
+	let name_end = inner.find(char::is_whitespace).unwrap_or(inner.len());
+	let name = inner[..name_end].to_ascii_lowercase();
+	match &*name {
+		"wbr" => None,
+		"br" => Some(Token::LineBreak),
+		_ => {
+			let attrs = parse_attributes(&inner[name_end..]);
+			let get_attr = |key: &str| attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+			let tag = Tag::from_element(&name, get_attr("class"), get_attr("href"));
+			Some(Token::Open { name, tag })
+		}
+	}
+}
+
+fn parse_attributes(mut raw: &str) -> Vec<(String, String)> {
+	let mut attrs = Vec::new();
+	loop {
+		raw = raw.trim_start();
+		if raw.is_empty() {
+			return attrs;
+		}
+		let key_end = raw.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(raw.len());
+		let key = raw[..key_end].to_ascii_lowercase();
+		raw = raw[key_end..].trim_start();
+		let value = if let Some(after_eq) = raw.strip_prefix('=') {
+			let after_eq = after_eq.trim_start();
+			let (value, remaining) = match after_eq.chars().next() {
+				Some(quote @ '"') | Some(quote @ '\'') => {
+					let body = &after_eq[1..];
+					let end = body.find(quote).unwrap_or(body.len());
+					(&body[..end], body.get(end + 1..).unwrap_or(""))
+				}
+				_ => {
+					let end = after_eq.find(char::is_whitespace).unwrap_or(after_eq.len());
+					(&after_eq[..end], &after_eq[end..])
+				}
+			};
+			raw = remaining;
+			decode_entities(value)
+		} else {
+			String::new()
+		};
+		attrs.push((key, value));
+	}
+}
+
+/// Decodes the HTML entities 4chan uses (`&gt;`, `&#039;`, etc.).
+/// Unknown entities are left as-is.
+pub fn decode_entities(text: &str) -> String {
+	let mut decoded = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(amp) = rest.find('&') {
+		decoded.push_str(&rest[..amp]);
+		rest = &rest[amp..];
+		// no real entity is anywhere near this long, so don't scan the whole string for a ';'
+		let semicolon = rest.char_indices().take(12).find(|&(_, c)| c == ';').map(|(i, _)| i);
+		let entity = semicolon.and_then(|end| {
+			let name = &rest[1..end];
+			let ch = match name {
+				"amp" => Some('&'),
+				"lt" => Some('<'),
+				"gt" => Some('>'),
+				"quot" => Some('"'),
+				"apos" => Some('\''),
+				"nbsp" => Some(' '),
+				_ => {
+					if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+						u32::from_str_radix(hex, 16).ok().and_then(std::char::from_u32)
+					} else if let Some(dec) = name.strip_prefix('#') {
+						dec.parse().ok().and_then(std::char::from_u32)
+					} else {
+						None
+					}
+				}
+			};
+			ch.map(|ch| (ch, end))
+		});
+		if let Some((ch, end)) = entity {
+			decoded.push(ch);
+			rest = &rest[end + 1..];
+		} else {
+			decoded.push('&');
+			rest = &rest[1..];
+		}
+	}
+	decoded.push_str(rest);
+	decoded
+}
+
+/// A quotelink inside of a `StyledComment`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkSpan {
+	// indices of the spans in `StyledComment::text` that make up the link's text
+	pub spans: Range<usize>,
+	pub href:  String,
+}
+
+/// A comment converted for display, along with where it's quotelinks ended up
+#[derive(Debug, Clone)]
+pub struct StyledComment {
+	pub text:  StyledString,
+	pub links: Vec<LinkSpan>,
+}
+
+/// Converts the raw HTML of `Post::com` into styled text for display in the terminal
+pub fn comment_to_styled(html: &str) -> StyledComment {
+	let mut styled = StyledString::new();
+	let mut links: Vec<LinkSpan> = Vec::new();
+	let mut open: Vec<(String, Tag)> = Vec::new();
+	for token in tokenize(html) {
+		match token {
+			Token::Text(text) => {
+				let style = Style::merge(&open.iter().map(|(_, tag)| tag.style()).collect::<Vec<_>>());
+				styled.append_styled(text, style);
+				if open.iter().any(|(_, tag)| matches!(tag, Tag::QuoteLink(_))) {
+					if let Some(link) = links.last_mut() {
+						link.spans.end = styled.spans_raw().len();
+					}
+				}
+			}
+			Token::LineBreak => styled.append_plain("\n"),
+			Token::Open { name, tag } => {
+				if let Tag::QuoteLink(href) = &tag {
+					let next_span = styled.spans_raw().len();
+					links.push(LinkSpan { spans: next_span..next_span, href: href.clone() });
+				}
+				open.push((name, tag));
+			}
+			Token::Close { name } => {
+				// unbalanced closing tags are ignored rather than closing everything
+				if let Some(i) = open.iter().rposition(|(n, _)| *n == name) {
+					let cross_thread = open[i..].iter().any(|(_, tag)| matches!(tag, Tag::QuoteLink(href) if href.contains("/thread/")));
+					open.truncate(i);
+					// same as 4chan's own extension, mark links that leave the current thread
+					if cross_thread {
+						styled.append_styled(" (Cross-thread)", Tag::Italic.style());
+					}
+				}
+			}
+		}
+	}
+	// a link with no text can't be selected
+	links.retain(|link| !link.spans.is_empty());
+	StyledComment { text: styled, links }
+}
+
+/// Converts the raw HTML of `Post::com` into plain text, keeping the line breaks
+pub fn comment_to_text(html: &str) -> String {
+	tokenize(html)
+		.into_iter()
+		.filter_map(|token| match token {
+			Token::Text(text) => Some(text),
+			Token::LineBreak => Some("\n".to_string()),
+			_ => None,
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use cursive::theme::{BaseColor, Color, ColorType, Effect};
+
+	use super::{Tag, Token, comment_to_styled, comment_to_text, decode_entities, tokenize};
+	use crate::data::Thread;
+
+	fn load_dummy() -> Thread {
+		let test = std::fs::read_to_string("assets/test/dummy.json").unwrap();
+		serde_json::de::from_str(&test).unwrap()
+	}
+
+	#[test]
+	fn test_decode_entities() {
+		assert_eq!(decode_entities("`unfolds&#039; them"), "`unfolds' them");
+		assert_eq!(decode_entities("&gt;&gt;570368 &amp; &quot;hi&quot; &#x41;"), ">>570368 & \"hi\" A");
+		assert_eq!(decode_entities("AT&T &bogus; &"), "AT&T &bogus; &");
+	}
+
+	#[test]
+	fn test_comment_to_text() {
+		assert_eq!(
+			comment_to_text("<a href=\"#p570368\" class=\"quotelink\">&gt;&gt;570368</a><br><span class=\"quote\">&gt;be me</span><br><b>AT&amp;T</b>"),
+			">>570368\n>be me\nAT&T"
+		);
+	}
+
+	#[test]
+	fn test_greentext_and_quotelinks() {
+		let tokens = tokenize("<a href=\"#p570368\" class=\"quotelink\">&gt;&gt;570368</a><br><span class=\"quote\">&gt;be me</span>");
+		assert_eq!(tokens, vec![
+			Token::Open { name: "a".into(), tag: Tag::QuoteLink("#p570368".into()) },
+			Token::Text(">>570368".into()),
+			Token::Close { name: "a".into() },
+			Token::LineBreak,
+			Token::Open { name: "span".into(), tag: Tag::Greentext },
+			Token::Text(">be me".into()),
+			Token::Close { name: "span".into() },
+		]);
+
+		let styled = comment_to_styled("<span class=\"quote\">&gt;be me</span><br>normal").text;
+		assert_eq!(styled.source(), ">be me\nnormal");
+		let green = styled.spans().find(|s| s.content == ">be me").unwrap();
+		assert_eq!(green.attr.color.front, ColorType::Color(Color::Light(BaseColor::Green)));
+		let normal = styled.spans().find(|s| s.content == "normal").unwrap();
+		assert_eq!(normal.attr.color.front, ColorType::InheritParent);
+	}
+
+	#[test]
+	fn test_dummy_comments() {
+		let thread = load_dummy();
+		for post in thread.posts.iter() {
+			let styled = comment_to_styled(post.com.as_ref().unwrap()).text;
+			assert!(!styled.source().contains('<'), "leftover markup in {}", styled.source());
+			assert!(!styled.source().contains("&gt;") && !styled.source().contains("&#039;"));
+		}
+
+		let faq = comment_to_styled(thread.posts[1].com.as_ref().unwrap()).text;
+		let source = faq.source();
+		assert!(source.starts_with("FAQs about papercraft\n\nWhat paper should I use?\n"));
+		// <wbr> is stripped without breaking up the link
+		assert!(source.contains("http://papercraft.wikidot.com/papercraft\n"));
+		assert!(source.contains("`unfolds' them"));
+
+		let bold = faq.spans().find(|s| s.content == "FAQs about papercraft").unwrap();
+		assert!(bold.attr.effects.contains(Effect::Bold));
+		let italic = faq.spans().find(|s| s.content == "What is Pepakura?").unwrap();
+		assert!(italic.attr.effects.contains(Effect::Italic));
+
+		// the cross-board link is nested inside of a regular link
+		let origami = comment_to_styled(thread.posts[2].com.as_ref().unwrap());
+		assert!(origami.text.source().contains("threads >>>/po/lft - open up"));
+		assert_eq!(origami.links.len(), 1);
+		assert_eq!(origami.links[0].href, "//boards.4channel.org/po/catalog#s=lft");
+	}
+
+	#[test]
+	fn test_cross_thread_marker() {
+		let styled = comment_to_styled(
+			"<a href=\"#p570368\" class=\"quotelink\">&gt;&gt;570368</a> and \
+			<a href=\"/g/thread/1#p2\" class=\"quotelink\">&gt;&gt;2</a>"
+		);
+		assert_eq!(styled.text.source(), ">>570368 and >>2 (Cross-thread)");
+		let link_text: Vec<_> = styled.links.iter()
+			.map(|link| styled.text.spans().skip(link.spans.start).take(link.spans.len()).map(|s| s.content).collect::<String>())
+			.collect();
+		assert_eq!(link_text, vec![">>570368", ">>2"]);
+	}
+}
@@ -2,25 +2,39 @@
 
 extern crate test;
 
-use std::{cell::RefCell, fs::File, ops::Deref, rc::Rc, time::Instant};
+use std::{cell::RefCell, fs::File, io::Write, ops::Deref, rc::Rc, time::{Duration, Instant}};
+
+use chrono::{Local, TimeZone};
 
 use bench_debug::log_bench;
 
 
 
-use cursive::{Cursive, Vec2, View, menu::{MenuItem, MenuTree}, theme::{BaseColor, Color}, traits::*, view::SizeConstraint, views::{Button, LinearLayout, ResizedView, ScrollView, SelectView, TextView}};
+use cursive::{CbSink, Cursive, Vec2, View, event::Key, menu::{MenuItem, MenuTree}, theme::{BaseColor, Color, Effect, Style}, traits::*, utils::markup::StyledString, view::SizeConstraint, views::{Button, Dialog, EditView, LinearLayout, OnEventView, ResizedView, ScrollView, SelectView, TextView}};
 
 
 use simplelog::{Config, LevelFilter, CombinedLogger, WriteLogger};
 use log::*;
 
+mod cli;
 mod data;
 mod views;
 mod config;
+mod html;
 mod net;
+mod quotes;
+mod cache;
+mod ratelimit;
+mod watcher;
+mod headless;
+mod notify;
+mod archive;
+mod export;
 
-use views::{Divider, ImageView, RenderMode, ScaleMode, traits::{Panelable, ResizableWeak}};
-use data::{BoardsResponse, Post};
+use views::{CommentView, Dithering, Divider, ImageView, ImageViewer, RenderMode, ScaleMode, traits::{Panelable, ResizableWeak}};
+use data::{AttachmentData, BoardsResponse, CatalogThread, Post, Thread};
+use quotes::{QuoteLink, ThreadIndex};
+use watcher::{ThreadStatus, WatchedThread, Watcher};
 
 
 
@@ -29,12 +43,48 @@ pub struct SettingsAndData {
 	show_nsfw: bool,
 	render_mode: RenderMode,
 	scale_mode: ScaleMode,
+	dithering: Dithering,
 	// data
 	boards: BoardsResponse,
+	watcher: Watcher,
+	// where the watch list is saved, if there is anywhere to save it
+	watch_list_path: Option<std::path::PathBuf>,
 }
 
 
 fn main() {
+	let args = match cli::parse_args(std::env::args().skip(1)) {
+		Ok(args) => args,
+		Err(e) => {
+			eprintln!("{}\n\n{}", e, cli::USAGE);
+			std::process::exit(2);
+		}
+	};
+	if args.help {
+		println!("{}", cli::USAGE);
+		return;
+	}
+	let mut app_config = match config::load_app_config(args.config.as_deref()) {
+		Ok(app_config) => app_config,
+		Err(e) => {
+			eprintln!("Failed to load config: {}", e);
+			std::process::exit(1);
+		}
+	};
+	// the command line takes priority over the environment, which takes priority over the config file
+	app_config.endpoints.apply_env();
+	let endpoint_args = [
+		(args.api_url, &mut app_config.endpoints.api),
+		(args.image_url, &mut app_config.endpoints.image),
+		(args.static_url, &mut app_config.endpoints.static_content),
+	];
+	for (arg, endpoint) in endpoint_args {
+		if let Some(url) = arg {
+			*endpoint = url;
+		}
+	}
+	net::set_endpoints(app_config.endpoints.clone());
+
 	// initialize logger
 	CombinedLogger::init(
 		vec![
@@ -45,13 +95,76 @@ fn main() {
 		]
 	).unwrap();
 
+	if app_config.cache.enabled {
+		match app_config.cache.dir.clone().or_else(config::cache_dir) {
+			Some(dir) => match cache::DiskCache::new(&dir, app_config.cache.max_size_mb * 1024 * 1024) {
+				Ok(cache) => {
+					info!("Using the cache in {} ({} bytes)", dir.display(), cache.size());
+					net::set_cache(cache);
+				}
+				Err(e) => warn!("Couldn't open the cache in {}, continuing without it: {}", dir.display(), e),
+			},
+			None => warn!("Couldn't find a cache directory, continuing without a cache"),
+		}
+	}
+
+	if let Some(command) = args.command {
+		let result = match command {
+			cli::Command::Watch(watch_args) => headless::run_watch(watch_args, &app_config),
+			cli::Command::Archive(archive_args) => headless::run_archive(archive_args, &app_config),
+			cli::Command::Export(export_args) => headless::run_export(export_args),
+		};
+		if let Err(e) = result {
+			error!("{}", e);
+			eprintln!("Error: {}", e);
+			std::process::exit(1);
+		}
+		return;
+	}
+
+	// the terminal has to be asked before cursive starts reading input
+	let capabilities = views::graphics::detect();
+	info!("Terminal graphics: {:?}", capabilities);
+	let color_depth = app_config.display.color_depth.unwrap_or_else(views::palette::detect);
+	info!("Drawing images with {:?} colors", color_depth);
+	views::palette::set_color_depth(color_depth);
+
 	let mut siv = cursive::default();
 
+	let watch_list_path = app_config.watch.list.clone().or_else(config::default_watch_list_path);
+	let watch_list = match &watch_list_path {
+		Some(path) => config::load_watch_list(path).unwrap_or_else(|e| {
+			warn!("Couldn't read the watch list {}: {}", path.display(), e);
+			Vec::new()
+		}),
+		None => Vec::new(),
+	};
+	let watcher = Watcher::new(watch_list);
+	let cb_sink = siv.cb_sink().clone();
+	let notifier = notify::Notifier::new(app_config.watch.notify.clone());
+	watcher.spawn(Duration::from_secs(app_config.watch.interval_secs), move |event| {
+		info!("Watcher: {}", event);
+		notifier.run_hook(&event);
+		let notification = notifier.terminal_notification(&event);
+		let _ = cb_sink.send(Box::new(move |c| {
+			// written from the UI thread so it can't end up in the middle of a redraw
+			if let Some(sequence) = notification {
+				let mut stdout = std::io::stdout();
+				let _ = stdout.write_all(sequence.as_bytes()).and_then(|_| stdout.flush());
+			}
+			refresh_watch_list(c);
+		}));
+	});
+
 	let settings = Rc::new(RefCell::new(SettingsAndData {
 		show_nsfw: false,
 		render_mode: RenderMode::Color,
 		scale_mode: ScaleMode::Linear,
-		boards: net::load_4chan_boards(),
+		dithering: Dithering::None,
+		// filled in by `load_boards` once the UI is up, so a failure can be shown in a dialog
+		boards: BoardsResponse { boards: Vec::new(), troll_flags: None },
+		watcher,
+		watch_list_path,
 	}));
 
 	siv.set_user_data(settings.clone());
@@ -98,14 +211,7 @@ fn main() {
 			}
 			settings.show_nsfw = !settings.show_nsfw;
 		}
-		c.call_on_name(
-			"boards_list",
-			|b_scrollable: &mut ScrollView<SelectView>| {
-				let b = b_scrollable.get_inner_mut();
-				b.clear();
-				add_boards_to_select(&(*settings).borrow(), b);
-			},
-		);
+		refresh_boards_list(c);
 	});
 
 	fn set_scale_mode(c: &mut Cursive, s: ScaleMode) {
@@ -114,6 +220,9 @@ fn main() {
 	fn set_render_mode(c: &mut Cursive, r: RenderMode) {
 		c.user_data::<Rc<RefCell<SettingsAndData>>>().unwrap().borrow_mut().render_mode = r;
 	}
+	fn set_dithering(c: &mut Cursive, d: Dithering) {
+		c.user_data::<Rc<RefCell<SettingsAndData>>>().unwrap().borrow_mut().dithering = d;
+	}
 	
 	settings_subtree.add_subtree(
 		"Image Settings",
@@ -127,23 +236,59 @@ fn main() {
 				.leaf("Cubic",            |c| set_scale_mode(c, ScaleMode::Cubic))
 				.leaf("Gaussian",         |c| set_scale_mode(c, ScaleMode::Gaussian))
 				.leaf("Lanczos",          |c| set_scale_mode(c, ScaleMode::Lanczos))
+			).subtree(
+				"Dithering",
+				MenuTree::new()
+					.leaf("None (Threshold)", |c| set_dithering(c, Dithering::None))
+					.leaf("Ordered (Bayer)",  |c| set_dithering(c, Dithering::Bayer))
+					.leaf("Floyd-Steinberg",  |c| set_dithering(c, Dithering::FloydSteinberg))
+					.leaf("Atkinson",         |c| set_dithering(c, Dithering::Atkinson))
 			).subtree(
 				"Render Mode", 
 				MenuTree::new()
 					.leaf("Color",                |c| set_render_mode(c, RenderMode::Color))
+					.leaf("Quadrants",            |c| set_render_mode(c, RenderMode::Quadrant))
+					.leaf("Sextants (needs a Unicode 13 font)", |c| set_render_mode(c, RenderMode::Sextant))
 					.leaf("Grayscale",            |c| set_render_mode(c, RenderMode::Grayscale))
+					.leaf("Sixel",                |c| set_render_mode(c, RenderMode::Sixel))
+					.leaf("Kitty",                |c| set_render_mode(c, RenderMode::Kitty))
+					.leaf("iTerm2 Inline",        |c| set_render_mode(c, RenderMode::ITermInline))
+					.leaf("Braille",              |c| set_render_mode(c, RenderMode::Braille))
+					.leaf("Braille (Color)",      |c| set_render_mode(c, RenderMode::BrailleColor))
 					.leaf("X11 (unimplemented!)", |c| set_render_mode(c, RenderMode::Gui))
 				)
 	);
 
 	siv.menubar().add_subtree("Settings", settings_subtree);
+	siv.menubar().add_leaf(watch_menu_label(0), show_watch_list);
 	siv.menubar()
 		.add_leaf("Press [ESC] to access the menu", |_| {});
 	siv.add_global_callback(cursive::event::Key::Esc, |c| c.select_menubar());
 	
 	add_board_key_nav_callbacks(&mut siv);
+	load_boards(&mut siv);
 	
-	siv.run();
+	let mut runner = siv.runner();
+	runner.refresh();
+	let mut redrawn = true;
+	while runner.is_running() {
+		// popups are smaller than the full screen layers below them, and images would be drawn over them
+		let layer_sizes = runner.screen().layer_sizes();
+		if views::graphics::set_suspended(layer_sizes.last() != layer_sizes.first()) {
+			runner.refresh();
+			redrawn = true;
+		}
+		if redrawn {
+			if let Err(e) = views::graphics::flush() {
+				warn!("Failed to draw images: {}", e);
+			}
+		}
+		// without an fps limit, cursive only redraws after an event or callback
+		redrawn = runner.step();
+	}
+	// kitty images would stay on screen otherwise
+	views::graphics::set_suspended(true);
+	let _ = views::graphics::flush();
 }
 
 fn add_board_key_nav_callbacks(siv: &mut Cursive) {
@@ -209,68 +354,501 @@ fn scroll_to_matching_board(scroll_wrap: &mut ScrollView<SelectView>, ch: char,
     scroll_wrap.scroll_to_important_area()
 }
 
-/// Creates a LinearLayout for
-fn create_and_add_thread_panel(op: &Post, board: impl AsRef<str>, render_mode: RenderMode, img_scale_method: ScaleMode) -> LinearLayout {
+/// The thread that the quotelinks of a comment are resolved against
+struct QuoteContext {
+	board:     String,
+	thread_no: isize,
+	// the thread, if it has already been loaded
+	thread:    Option<Rc<Thread>>,
+}
+
+/// Creates a LinearLayout for a catalog entry, with a button that opens the full thread
+fn create_and_add_thread_panel(entry: &CatalogThread, page_count: usize, board: impl AsRef<str>, render_mode: RenderMode, img_scale_method: ScaleMode, dithering: Dithering, cb_sink: &CbSink) -> LinearLayout {
+	let op = &entry.op;
+	let board = board.as_ref().to_string();
 	let mut thread_panel = LinearLayout::horizontal();
-	if let Some(attachment) = &op.attachment {
-		if !attachment.filedeleted {
-			thread_panel.add_child(
-				ImageView::new(
-					format!("https://i.4cdn.org/{}/{}s.jpg", board.as_ref(), &attachment.tim), 
-					Vec2::new(20, 10), 
-					render_mode, 
-					img_scale_method
-				)
-			);
-		}
+	let context = Rc::new(QuoteContext { board: board.clone(), thread_no: op.no, thread: None });
+	if let Some(thumbnail) = create_thumbnail(op, &context, render_mode, img_scale_method, dithering, cb_sink) {
+		thread_panel.add_child(thumbnail);
 	}
-	//TODO: implement thread viewing
 	let mut text_pane = LinearLayout::vertical();
+	let no = op.no;
 	text_pane.add_child(
 		Button::new_raw(
 			op.op_data.as_ref().unwrap().sub.as_ref()
 				.unwrap_or(&"Thread".to_string()), 
-			|_| {}
+			move |c| open_thread_view(c, &board, no)
 		)
 	);
-	text_pane.add_child(
-		TextView::new(op.com.as_ref().unwrap_or(&"".to_string()))
-	);
+	// threads on the last page are the next ones to be pruned
+	let page_color = if entry.page >= page_count { Color::Light(BaseColor::Red) } else { Color::Dark(BaseColor::White) };
+	text_pane.add_child(TextView::new(StyledString::styled(format!("page {}/{}", entry.page, page_count), page_color)));
+	text_pane.add_child(create_comment_view(op, &context));
 
 	thread_panel.add_child(text_pane);
 	thread_panel
 }
 
+/// Creates the thumbnail for a post's attachment, if it has one that hasn't been deleted.
+/// Enter on the thumbnail opens the full size image.
+fn create_thumbnail(post: &Post, context: &Rc<QuoteContext>, render_mode: RenderMode, img_scale_method: ScaleMode, dithering: Dithering, cb_sink: &CbSink) -> Option<ImageView> {
+	match &post.attachment {
+		Some(attachment) if !attachment.filedeleted => {
+			let url = if attachment.spoiler {
+				net::spoiler_url(&context.board, attachment.custom_spoiler)
+			} else {
+				net::thumbnail_url(&context.board, attachment.tim)
+			};
+			let (context, attachment) = (context.clone(), attachment.clone());
+			Some(
+				ImageView::load(url, Vec2::new(20, 10), render_mode, img_scale_method, dithering, cb_sink.clone())
+					.on_submit(move |c| open_image_viewer(c, &context, &attachment))
+			)
+		}
+		_ => None,
+	}
+}
+
+/// Shows `attachment` at full size on a new fullscreen layer.
+/// The other images of the thread can be flipped through from there, if it has been loaded.
+fn open_image_viewer(c: &mut Cursive, context: &QuoteContext, attachment: &AttachmentData) {
+	let (scale_method, render_mode, dithering) = {
+		let settings = get_settings(c).unwrap();
+		(settings.scale_mode, settings.render_mode, settings.dithering)
+	};
+	let attachments: Vec<AttachmentData> = context.thread.iter()
+		.flat_map(|thread| thread.posts.iter())
+		.filter_map(|post| post.attachment.clone())
+		.filter(|attachment| !attachment.filedeleted)
+		.collect();
+	let (attachments, current) = match attachments.iter().position(|other| other.tim == attachment.tim) {
+		Some(current) => (attachments, current),
+		// catalog entries don't have their thread loaded
+		None => (vec![attachment.clone()], 0),
+	};
+	c.add_fullscreen_layer(views::graphics::Layer::new(
+		OnEventView::new(ImageViewer::new(&context.board, attachments, current, render_mode, scale_method, dithering, c.cb_sink().clone()))
+			.on_event('q', |c| { c.pop_layer(); })
+			.on_event(Key::Backspace, |c| { c.pop_layer(); })
+			.on_event(Key::Esc, |c| { c.pop_layer(); }),
+	));
+}
+
+/// Creates the view for a post's comment, with quotelinks that open a preview of the quoted post
+fn create_comment_view(post: &Post, context: &Rc<QuoteContext>) -> CommentView {
+	let context = context.clone();
+	CommentView::new(post.com.as_deref().unwrap_or(""), &context.board, context.thread_no)
+		.on_link(move |c, link| show_quote_preview(c, &context, link))
+}
+
+/// Builds the header line of a post: name, tripcode, poster ID, capcode and timestamp.
+/// The post number follows it as a button, see `create_post_panel`.
+fn create_post_header(post: &Post) -> StyledString {
+	let mut header = StyledString::new();
+	header.append_styled(
+		post.name.as_deref().unwrap_or("Anonymous"),
+		Style::from(Color::Light(BaseColor::Green)).combine(Effect::Bold),
+	);
+	if let Some(trip) = &post.trip {
+		header.append_styled(format!(" {}", trip), Color::Dark(BaseColor::Green));
+	}
+	if let Some(id) = &post.id {
+		header.append_plain(format!(" (ID: {})", id));
+	}
+	if let Some(capcode) = &post.capcode {
+		header.append_styled(format!(" ## {}", capcode), Color::Light(BaseColor::Red));
+	}
+	let posted = Local.timestamp(post.time as i64, 0);
+	header.append_plain(format!(" {} ", posted.format("%Y-%m-%d %H:%M:%S")));
+	header
+}
+
+/// Builds the "Replies: >>a >>b" line shown under a post that has been quoted.
+/// The replies are written as regular quotelinks so they can be previewed like any other.
+fn create_replies_line(replies: &[isize]) -> String {
+	let mut line = "Replies:".to_string();
+	for reply in replies {
+		line.push_str(&format!(" <a href=\"#p{0}\" class=\"quotelink\">&gt;&gt;{0}</a>", reply));
+	}
+	line
+}
+
+/// Creates a LinearLayout for a single post inside of a thread view
+fn create_post_panel(post: &Post, context: &Rc<QuoteContext>, index: &ThreadIndex, render_mode: RenderMode, img_scale_method: ScaleMode, dithering: Dithering, cb_sink: &CbSink) -> LinearLayout {
+	let mut post_panel = LinearLayout::horizontal();
+	if let Some(thumbnail) = create_thumbnail(post, context, render_mode, img_scale_method, dithering, cb_sink) {
+		post_panel.add_child(thumbnail);
+	}
+	let mut text_pane = LinearLayout::vertical();
+	// Enter on the post number marks the post as your own, so replies to it are reported
+	let (you_context, no) = (context.clone(), post.no);
+	text_pane.add_child(
+		LinearLayout::horizontal()
+			.child(TextView::new(create_post_header(post)))
+			.child(Button::new_raw(format!("No.{}", post.no), move |c| toggle_you(c, &you_context.board, you_context.thread_no, no)))
+	);
+	text_pane.add_child(create_comment_view(post, context));
+	let replies = index.replies(post.no);
+	if !replies.is_empty() {
+		let context = context.clone();
+		text_pane.add_child(
+			CommentView::new(&create_replies_line(replies), &context.board, context.thread_no)
+				.on_link(move |c, link| show_quote_preview(c, &context, link))
+		);
+	}
+
+	post_panel.add_child(text_pane);
+	post_panel
+}
+
+/// Fetches a thread and shows every post in it on a new fullscreen layer.
+/// The layer is closed again with `q` or backspace.
+fn open_thread_view(c: &mut Cursive, board: &str, no: isize) {
+	let (scale_method, render_mode, dithering) = {
+		let settings = c.user_data::<Rc<RefCell<SettingsAndData>>>().unwrap().borrow();
+		(settings.scale_mode, settings.render_mode, settings.dithering)
+	};
+	let thread = match net::get_thread(board, no, net::Priority::User) {
+		Ok(thread) => Rc::new(thread),
+		Err(e) => {
+			let board = board.to_string();
+			show_net_error(c, &format!("Couldn't load thread /{}/{}", board, no), e, move |c| open_thread_view(c, &board, no));
+			return;
+		}
+	};
+	let index = ThreadIndex::new(board, &thread);
+	let context = Rc::new(QuoteContext { board: board.to_string(), thread_no: no, thread: Some(thread.clone()) });
+
+	let mut posts_view = LinearLayout::vertical();
+	for (i, post) in thread.posts.iter().enumerate() {
+		if i > 0 {
+			posts_view.add_child(Divider::horizontal());
+		}
+		posts_view.add_child(create_post_panel(post, &context, &index, render_mode, scale_method, dithering, c.cb_sink()));
+	}
+
+	let title = thread.posts.first()
+		.and_then(|op| op.op_data.as_ref())
+		.and_then(|op_data| op_data.sub.clone())
+		.unwrap_or_else(|| format!("No.{}", no));
+	c.add_fullscreen_layer(views::graphics::Layer::new(
+		OnEventView::new(
+			posts_view
+				.scrollable()
+				.in_panel()
+				.title(format!("/{}/ - {} [q: back, w: watch, e: export, Enter on >>link: preview, Enter on No.: mark (You)]", board, title)),
+		)
+		.on_event('q', |c| { c.pop_layer(); })
+		.on_event(Key::Backspace, |c| { c.pop_layer(); })
+		.on_event('w', move |c| toggle_watch(c, &context.board, no))
+		.on_event('e', {
+			let board = board.to_string();
+			move |c| show_export_menu(c, &board, thread.clone())
+		}),
+	));
+	get_watcher(c).mark_read(board, no);
+	refresh_watch_list(c);
+}
+
+/// Lets the user pick a format to export the thread in
+fn show_export_menu(c: &mut Cursive, board: &str, thread: Rc<Thread>) {
+	let mut formats = SelectView::new()
+		.item("Markdown", export::Format::Markdown)
+		.item("Plain text", export::Format::Text)
+		.item("HTML (images from the archive)", export::Format::Html);
+	let board = board.to_string();
+	formats.set_on_submit(move |c, format: &export::Format| {
+		c.pop_layer();
+		show_export_path(c, &board, thread.clone(), *format);
+	});
+	c.add_layer(
+		OnEventView::new(Dialog::around(formats).title("Export Thread").dismiss_button("Cancel"))
+			.on_event(Key::Esc, |c| { c.pop_layer(); }),
+	);
+}
+
+/// Asks where to save the exported thread, and saves it there
+fn show_export_path(c: &mut Cursive, board: &str, thread: Rc<Thread>, format: export::Format) {
+	let no = thread.posts.first().map_or(0, |op| op.no);
+	let board = board.to_string();
+	let default_path = format!("{}-{}.{}", board, no, format.extension());
+	c.add_layer(
+		Dialog::around(EditView::new().content(default_path).with_name("export_path").fixed_width(50))
+			.title("Save As")
+			.button("Save", move |c| {
+				let path = c.call_on_name("export_path", |path: &mut EditView| path.get_content()).unwrap();
+				c.pop_layer();
+				match std::fs::write(&*path, export::export(format, &board, &thread)) {
+					Ok(()) => c.add_layer(Dialog::info(format!("Saved to {}", path))),
+					Err(e) => c.add_layer(Dialog::info(format!("Couldn't write {}: {}", path, e)).title("Export Failed")),
+				}
+			})
+			.dismiss_button("Cancel"),
+	);
+}
+
+fn get_watcher(c: &mut Cursive) -> Watcher {
+	c.user_data::<Rc<RefCell<SettingsAndData>>>().unwrap().borrow().watcher.clone()
+}
+
+/// Starts or stops watching a thread. Posts that are already there don't count as new.
+fn toggle_watch(c: &mut Cursive, board: &str, no: isize) {
+	let watcher = get_watcher(c);
+	if watcher.is_watched(board, no) {
+		watcher.remove(board, no);
+	} else {
+		let thread = match net::get_thread(board, no, net::Priority::User) {
+			Ok(thread) => thread,
+			Err(e) => {
+				let board = board.to_string();
+				show_net_error(c, &format!("Couldn't watch thread /{}/{}", board, no), e, move |c| toggle_watch(c, &board, no));
+				return;
+			}
+		};
+		let name = thread.posts.first()
+			.and_then(|op| op.op_data.as_ref())
+			.and_then(|op_data| op_data.sub.clone())
+			.unwrap_or_else(|| format!("No.{}", no));
+		watcher.add(config::ThreadConfig {
+			board: board.to_string(),
+			id: no,
+			name,
+			last_modified: config::get_unix_epoch(),
+			last_seen: thread.posts.iter().map(|post| post.no).max().unwrap_or(0),
+			you: Vec::new(),
+		});
+	}
+	refresh_watch_list(c);
+}
+
+/// Marks a post as one of your own (or unmarks it) so the watcher reports replies to it.
+/// The thread is watched first if it isn't already.
+fn toggle_you(c: &mut Cursive, board: &str, thread: isize, post: isize) {
+	let watcher = get_watcher(c);
+	if !watcher.is_watched(board, thread) {
+		toggle_watch(c, board, thread);
+	}
+	// watching fails if the thread couldn't be loaded, that's already been shown
+	let message = match watcher.toggle_you(board, thread, post) {
+		Some(true) => format!(">>{} is marked as your post, replies to it will be reported", post),
+		Some(false) => format!(">>{} is no longer marked as your post", post),
+		None => return,
+	};
+	refresh_watch_list(c);
+	c.add_layer(Dialog::info(message).title("(You)"));
+}
+
+// position of the watched threads entry in the menubar, after "Quit" and "Settings"
+const WATCH_MENU_INDEX: usize = 2;
+
+fn watch_menu_label(unread: usize) -> String {
+	match unread {
+		0 => "Watched Threads".to_string(),
+		n => format!("Watched Threads ({})", n),
+	}
+}
+
+fn watched_thread_label(watched: &WatchedThread) -> String {
+	let mut label = format!("/{}/{} - {}", watched.config.board, watched.config.id, watched.config.name);
+	match (watched.unread, watched.replies) {
+		(0, _) => {}
+		(unread, 0) => label.push_str(&format!(" ({} new)", unread)),
+		(unread, replies) => label.push_str(&format!(" ({} new, {} (You))", unread, replies)),
+	}
+	match watched.status {
+		ThreadStatus::Alive => {}
+		ThreadStatus::Archived => label.push_str(" [archived]"),
+		ThreadStatus::NotFound => label.push_str(" [404]"),
+	}
+	label
+}
+
+fn fill_watch_list(watcher: &Watcher, list: &mut SelectView<(String, isize)>) {
+	let selected = list.selected_id();
+	list.clear();
+	for watched in watcher.threads() {
+		list.add_item(watched_thread_label(&watched), (watched.config.board.clone(), watched.config.id));
+	}
+	if let Some(i) = selected {
+		list.set_selection(i.min(list.len().saturating_sub(1)));
+	}
+}
+
+/// Shows the watched threads with their unread counts. Enter opens a thread, `d` stops watching it.
+fn show_watch_list(c: &mut Cursive) {
+	let watcher = get_watcher(c);
+	let mut list = SelectView::new();
+	fill_watch_list(&watcher, &mut list);
+	list.set_on_submit(|c, (board, no): &(String, isize)| {
+		c.pop_layer();
+		open_thread_view(c, board, *no);
+	});
+	c.add_layer(
+		OnEventView::new(
+			Dialog::around(list.with_name("watch_list").scrollable())
+				.title("Watched Threads [Enter: open, d: unwatch, w in a thread: watch]")
+				.dismiss_button("Close"),
+		)
+		.on_event(Key::Esc, |c| { c.pop_layer(); })
+		.on_event('d', |c| {
+			let selected = c.call_on_name("watch_list", |list: &mut SelectView<(String, isize)>| list.selection());
+			if let Some(Some(selected)) = selected {
+				get_watcher(c).remove(&selected.0, selected.1);
+				refresh_watch_list(c);
+			}
+		}),
+	);
+}
+
+/// Updates the unread counts in the menubar and the watched threads list (if it's open),
+/// and saves the watch list so the next start knows which posts were already seen
+fn refresh_watch_list(c: &mut Cursive) {
+	let watcher = get_watcher(c);
+	let path = c.user_data::<Rc<RefCell<SettingsAndData>>>().unwrap().borrow().watch_list_path.clone();
+	if let Some(path) = path {
+		if let Err(e) = config::save_watch_list(&path, &watcher.configs()) {
+			warn!("Couldn't save the watch list to {}: {}", path.display(), e);
+		}
+	}
+	c.menubar().remove(WATCH_MENU_INDEX);
+	c.menubar().insert_leaf(WATCH_MENU_INDEX, watch_menu_label(watcher.unread()), show_watch_list);
+	c.call_on_name("watch_list", |list: &mut SelectView<(String, isize)>| fill_watch_list(&watcher, list));
+}
+
+/// Shows the post a quotelink points to in a popup.
+/// The post is looked up in the already loaded thread first, and only fetched if it isn't there
+/// (cross-thread links, or quotes in the catalog). Links inside the preview open further previews on top of it.
+fn show_quote_preview(c: &mut Cursive, context: &Rc<QuoteContext>, link: &QuoteLink) {
+	let (board, thread_no, post_no) = match link {
+		QuoteLink::Post(post) => (context.board.clone(), context.thread_no, *post),
+		QuoteLink::CrossThread { board, thread, post } => (board.clone(), *thread, *post),
+		QuoteLink::Board { board, search } => {
+			let text = match search {
+				Some(search) => format!("Catalog search for \"{}\" on /{}/", search, board),
+				None => format!("Board /{}/", board),
+			};
+			c.add_layer(Dialog::info(text).title(format!(">>>/{}/", board)));
+			return;
+		}
+	};
+	let (scale_method, render_mode, dithering) = {
+		let settings = get_settings(c).unwrap();
+		(settings.scale_mode, settings.render_mode, settings.dithering)
+	};
+
+	let loaded = context.thread.as_ref()
+		.filter(|_| board == context.board && thread_no == context.thread_no)
+		.filter(|thread| thread.posts.iter().any(|post| post.no == post_no));
+	let thread = match loaded {
+		Some(thread) => thread.clone(),
+		None => match net::get_thread(&board, thread_no, net::Priority::User) {
+			Ok(thread) => Rc::new(thread),
+			Err(e) => {
+				let (context, link) = (context.clone(), link.clone());
+				show_net_error(c, &format!("Couldn't load >>{}", post_no), e, move |c| show_quote_preview(c, &context, &link));
+				return;
+			}
+		},
+	};
+
+	if let Some(post) = thread.posts.iter().find(|post| post.no == post_no) {
+		let index = ThreadIndex::new(&board, &thread);
+		let preview_context = Rc::new(QuoteContext { board: board.clone(), thread_no, thread: Some(thread.clone()) });
+		c.add_layer(
+			OnEventView::new(
+				Dialog::around(create_post_panel(post, &preview_context, &index, render_mode, scale_method, dithering, c.cb_sink()).scrollable())
+					.title(format!("/{}/ >>{}", board, post_no))
+					.dismiss_button("Close"),
+			)
+			.on_event(Key::Esc, |c| { c.pop_layer(); }),
+		);
+	} else {
+		c.add_layer(Dialog::info(format!("Post >>{} was not found, it may have been deleted", post_no)));
+	}
+}
+
 #[log_bench]
 fn create_board_view(c: &mut Cursive) -> impl View {
 	let mut layout = SelectView::new();
 
 	add_boards_to_select(&get_settings(c).unwrap(), &mut layout);
 
-	layout.set_on_submit(|c, board: &String| {
-		let (scale_method, render_mode) = {
-			let settings = c.user_data::<Rc<RefCell<SettingsAndData>>>().unwrap().borrow();
-			(settings.scale_mode, settings.render_mode)
-		};
-		c.call_on_name("threads_list", |threads_view: &mut LinearLayout| {
-			//TODO: There's probably a more idiomatic way to clear the LinearLayout
-			while threads_view.get_child(0).is_some() { threads_view.remove_child(0); };
-			let threads = net::get_threads_for_board(board);
-			let mut iter = threads.iter();
-			
-
-			if let Some(post) = iter.next() {
-				threads_view.add_child(create_and_add_thread_panel(post, board, render_mode, scale_method));
-			}
+	layout.set_on_submit(|c, board: &String| show_board(c, board));
+	layout.scrollable().with_name("boards_list")
+}
 
-			for post in iter {
-				threads_view.add_child(Divider::horizontal());
-				// if i > 5 {break} // TODO: Remove this
-				threads_view.add_child(create_and_add_thread_panel(post, board, render_mode, scale_method));
-			}
-		});
+/// Loads the catalog of `board` into the threads list
+fn show_board(c: &mut Cursive, board: &str) {
+	let (scale_method, render_mode, dithering) = {
+		let settings = c.user_data::<Rc<RefCell<SettingsAndData>>>().unwrap().borrow();
+		(settings.scale_mode, settings.render_mode, settings.dithering)
+	};
+	let catalog = match net::get_threads_for_board(board) {
+		Ok(catalog) => catalog,
+		Err(e) => {
+			let board = board.to_string();
+			show_net_error(c, &format!("Couldn't load /{}/", board), e, move |c| show_board(c, &board));
+			return;
+		}
+	};
+	let cb_sink = c.cb_sink().clone();
+	c.call_on_name("threads_list", |threads_view: &mut LinearLayout| {
+		//TODO: There's probably a more idiomatic way to clear the LinearLayout
+		while threads_view.get_child(0).is_some() { threads_view.remove_child(0); };
+		let mut iter = catalog.threads.iter();
+		
+
+		if let Some(entry) = iter.next() {
+			threads_view.add_child(create_and_add_thread_panel(entry, catalog.page_count, board, render_mode, scale_method, dithering, &cb_sink));
+		}
+
+		for entry in iter {
+			threads_view.add_child(Divider::horizontal());
+			// if i > 5 {break} // TODO: Remove this
+			threads_view.add_child(create_and_add_thread_panel(entry, catalog.page_count, board, render_mode, scale_method, dithering, &cb_sink));
+		}
 	});
-	layout.scrollable().with_name("boards_list")
+}
+
+/// Fetches the list of boards and fills the boards list with it
+fn load_boards(c: &mut Cursive) {
+	match net::load_4chan_boards() {
+		Ok(boards) => {
+			c.user_data::<Rc<RefCell<SettingsAndData>>>().unwrap().borrow_mut().boards = boards;
+			refresh_boards_list(c);
+		}
+		Err(e) => show_net_error(c, "Couldn't load the list of boards", e, load_boards),
+	}
+}
+
+/// Shows a dialog explaining a failed request, with a button to try again
+fn show_net_error<F>(c: &mut Cursive, context: &str, error: net::Error, retry: F)
+where
+	F: Fn(&mut Cursive) + 'static,
+{
+	warn!("{}: {}", context, error);
+	c.add_layer(
+		Dialog::text(format!("{}:\n{}", context, error))
+			.title("Network Error")
+			.button("Retry", move |c| {
+				c.pop_layer();
+				retry(c);
+			})
+			.dismiss_button("Cancel"),
+	);
+}
+
+/// Repopulates the boards list from the current settings
+fn refresh_boards_list(c: &mut Cursive) {
+	let settings = c.user_data::<Rc<RefCell<SettingsAndData>>>().unwrap().clone();
+	c.call_on_name(
+		"boards_list",
+		|b_scrollable: &mut ScrollView<SelectView>| {
+			let b = b_scrollable.get_inner_mut();
+			b.clear();
+			add_boards_to_select(&(*settings).borrow(), b);
+		},
+	);
 }
 
 pub fn add_boards_to_select(settings: &SettingsAndData, layout: &mut SelectView) {
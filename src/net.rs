@@ -1,15 +1,18 @@
-use std::{sync::{Arc, Mutex, Once}, thread, time::{Duration, Instant}};
+use std::{fmt::Display, sync::{Once, OnceLock}, time::{Duration, Instant}};
 
-use reqwest::blocking::Response;
-use serde::{Deserialize, Serialize};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use reqwest::{StatusCode, blocking::Response, header::{ETAG, HeaderName, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED}};
+use serde::de::DeserializeOwned;
 
 use log::*;
 
 use bench_debug::log_bench;
 
-use crate::data::{BoardsResponse, Post, Thread};
-use crate::config::ThreadConfig;
+use crate::cache::DiskCache;
+use crate::ratelimit::RateLimiter;
+pub use crate::ratelimit::Priority;
+use crate::data::{BoardsResponse, Catalog, CatalogPage, Thread};
+use crate::config::Endpoints;
 
 
 
@@ -23,105 +26,239 @@ fn get_client() -> &'static reqwest::blocking::Client {
 	}
 }
 
+static ENDPOINTS: OnceLock<Endpoints> = OnceLock::new();
+
+/// Sets the servers all requests go to. Only has an effect before the first request is made.
+pub fn set_endpoints(endpoints: Endpoints) {
+	if ENDPOINTS.set(endpoints).is_err() {
+		warn!("Endpoints were set after they were already in use");
+	}
+}
+
+fn endpoints() -> &'static Endpoints {
+	ENDPOINTS.get_or_init(Endpoints::default)
+}
+
+fn join_url(base: &str, path: &str) -> String {
+	format!("{}/{}", base.trim_end_matches('/'), path.trim_start_matches('/'))
+}
+
+/// URL of `path` (e.g. `boards.json`) on the JSON API
+pub fn api_url(path: impl AsRef<str>) -> String {
+	join_url(&endpoints().api, path.as_ref())
+}
+
+/// URL of the thumbnail of the attachment with upload time `tim`
+pub fn thumbnail_url(board: &str, tim: isize) -> String {
+	join_url(&endpoints().image, &format!("{}/{}s.jpg", board, tim))
+}
+
+/// URL of the full-size attachment with upload time `tim` and extension `ext` (including the dot)
+pub fn image_url(board: &str, tim: isize, ext: &str) -> String {
+	join_url(&endpoints().image, &format!("{}/{}{}", board, tim, ext))
+}
+
+/// URL of the image shown instead of a spoilered thumbnail
+pub fn spoiler_url(board: &str, custom_spoiler: Option<isize>) -> String {
+	let file = match custom_spoiler {
+		Some(n) => format!("image/spoiler-{}{}.png", board, n),
+		None => "image/spoiler.png".to_string(),
+	};
+	join_url(&endpoints().static_content, &file)
+}
+
+/// Everything that can go wrong while talking to the 4chan API
+#[derive(Debug)]
+pub enum Error {
+	// the request couldn't be sent or no response arrived (connection failures, timeouts, ...)
+	Transport(reqwest::Error),
+	// the server responded with an unexpected status code
+	Status(StatusCode),
+	// the response body wasn't in the format we expected
+	Decode(serde_json::Error),
+	// the board or thread doesn't exist, most likely because the thread was pruned or deleted
+	NotFound,
+}
+
+impl std::error::Error for Error {}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Error::Transport(e) if e.is_timeout() => write!(f, "the request timed out"),
+			Error::Transport(e) => write!(f, "the request failed: {}", e),
+			Error::Status(status) => write!(f, "the server responded with {}", status),
+			Error::Decode(e) => write!(f, "the response couldn't be read: {}", e),
+			Error::NotFound => write!(f, "not found (the thread may have been pruned or deleted)"),
+		}
+	}
+}
+
+impl From<reqwest::Error> for Error {
+	fn from(e: reqwest::Error) -> Self {
+		Error::Transport(e)
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(e: serde_json::Error) -> Self {
+		Error::Decode(e)
+	}
+}
+
+/// Maps error status codes of a response to `Error`s
+fn check_status(resp: Response) -> Result<Response, Error> {
+	match resp.status() {
+		StatusCode::NOT_FOUND => Err(Error::NotFound),
+		status if !status.is_success() && status != StatusCode::NOT_MODIFIED => Err(Error::Status(status)),
+		_ => Ok(resp),
+	}
+}
+
+// 4chan's API rules ask for at most one request per second
+static API_LIMITER: RateLimiter = RateLimiter::new(Duration::from_secs(1));
+// the image servers don't have a documented limit, but a catalog asks for ~150 thumbnails at once
+static IMAGE_LIMITER: RateLimiter = RateLimiter::new(Duration::from_millis(50));
+
+static CACHE: OnceLock<DiskCache> = OnceLock::new();
+
+/// Makes all following requests go through `cache`
+pub fn set_cache(cache: DiskCache) {
+	if CACHE.set(cache).is_err() {
+		warn!("The HTTP cache was set more than once");
+	}
+}
+
+fn header_string(resp: &Response, name: HeaderName) -> Option<String> {
+	resp.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+/// GETs `url` through the disk cache (if there is one), waiting for `limiter` before anything is sent.
+/// Cached copies of `immutable` resources are used without asking the server, anything else is
+/// revalidated with `If-None-Match`/`If-Modified-Since` and only downloaded again if it changed.
+fn fetch(url: &str, immutable: bool, limiter: &RateLimiter, priority: Priority) -> Result<bytes::Bytes, Error> {
+	let cached = CACHE.get().and_then(|cache| cache.get(url));
+	let mut req = get_client().get(url);
+	if let Some(entry) = &cached {
+		if immutable {
+			return Ok(entry.body.clone().into());
+		}
+		if let Some(etag) = &entry.meta.etag {
+			req = req.header(IF_NONE_MATCH, etag);
+		}
+		if let Some(last_modified) = &entry.meta.last_modified {
+			req = req.header(IF_MODIFIED_SINCE, last_modified);
+		}
+	}
+	let req = req.build()?;
+	limiter.acquire(priority);
+	let resp = check_status(get_client().execute(req)?)?;
+	if resp.status() == StatusCode::NOT_MODIFIED {
+		// we only send conditional requests when there is a cached copy
+		return cached
+			.map(|entry| entry.body.into())
+			.ok_or(Error::Status(StatusCode::NOT_MODIFIED));
+	}
+	let etag = header_string(&resp, ETAG);
+	let last_modified = header_string(&resp, LAST_MODIFIED);
+	let body = resp.bytes()?;
+	if let Some(cache) = CACHE.get() {
+		if let Err(e) = cache.put(url, &body, etag, last_modified) {
+			warn!("Failed to cache {}: {}", url, e);
+		}
+	}
+	Ok(body)
+}
+
+/// GETs `url` from the API and parses the response as JSON
+fn get_json<T: DeserializeOwned>(url: &str, priority: Priority) -> Result<T, Error> {
+	Ok(serde_json::from_slice(&fetch(url, false, &API_LIMITER, priority)?)?)
+}
+
+/// Downloads an image. Uploaded files never change, so a cached copy is used if there is one.
 #[log_bench(url)]
-pub fn get_bytes(url: impl AsRef<str> + std::fmt::Debug) -> bytes::Bytes {
-	let url = url.as_ref();
-	let req = get_client()
-		.get(url)
-		.build()
-		.expect(&*format!("Failed to build {} request", url));
-	let resp = get_client()
-		.execute(req)
-		.expect(&*format!("Error during {} request", url));
-	resp.bytes().unwrap()
+pub fn get_bytes(url: impl AsRef<str> + std::fmt::Debug) -> Result<bytes::Bytes, Error> {
+	fetch(url.as_ref(), true, &IMAGE_LIMITER, Priority::User)
 }
 
-pub fn load_4chan_boards() -> BoardsResponse {
+pub fn load_4chan_boards() -> Result<BoardsResponse, Error> {
 	let now = Instant::now();
-	let client = get_client();
 	
-	let resp = client.execute(
-		client
-			.get("https://a.4cdn.org/boards.json")
-			.build()
-			.expect("Failed to build boards list request")
-		).expect("Error requesting boards list");
+	let boards = get_json(&api_url("boards.json"), Priority::User)?;
 
 	info!("Took {:.4} seconds to get 4chan boards", now.elapsed().as_secs_f64());
 
-	resp
-		.json::<BoardsResponse>()
-		.expect("Failed to parse boards list")
+	Ok(boards)
 }
 
-pub fn request_url(url: impl AsRef<str>) -> Response {
-	let req = get_client()
-	.get(url.as_ref())
-	.build()
-	.expect("Failed to build threads list request");
+/// Loads every page of a board's catalog
+pub fn get_threads_for_board(board: impl Into<String>) -> Result<Catalog, Error> {
+	let s = board.into();
+	let now = Instant::now();
 	
-	get_client()
-	.execute(req)
-	.expect("Error requesting threads list")
+	let pages = get_json::<Vec<CatalogPage>>(&api_url(format!("{}/catalog.json", s)), Priority::User)?;
+
+	info!("Took {:.4} seconds to get /{}/ catalog", now.elapsed().as_secs_f64(), s);
+
+	Ok(Catalog::from(pages))
 }
 
-pub fn get_threads_for_board(board: impl Into<String>) -> Vec<Post> {
-	let s = board.into();
-	#[derive(Serialize, Deserialize)]
+/// Loads a thread. Background refreshes should use `Priority::Background` so they don't hold up the UI.
+pub fn get_thread(board: impl AsRef<str>, no: isize, priority: Priority) -> Result<Thread, Error> {
+	Ok(serde_json::from_slice(&get_thread_json(board, no, priority)?)?)
+}
+
+/// Formats a time the way HTTP headers like `If-Modified-Since` expect it
+fn http_date(time: DateTime<Utc>) -> String {
+	time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
 
-	struct CatalogPage {
-		page: usize,
-		threads: Vec<Post>,
+/// Loads a thread, or returns `None` if it hasn't changed since `since`.
+/// With the disk cache enabled this is the same as `get_thread`, the cache already revalidates it's copy.
+pub fn get_thread_since(board: impl AsRef<str>, no: isize, since: DateTime<Utc>, priority: Priority) -> Result<Option<Thread>, Error> {
+	let board = board.as_ref();
+	if CACHE.get().is_some() {
+		return get_thread(board, no, priority).map(Some);
 	}
+	let req = get_client()
+		.get(api_url(format!("{}/thread/{}.json", board, no)))
+		.header(IF_MODIFIED_SINCE, http_date(since))
+		.build()?;
+	API_LIMITER.acquire(priority);
+	let resp = check_status(get_client().execute(req)?)?;
+	if resp.status() == StatusCode::NOT_MODIFIED {
+		return Ok(None);
+	}
+	Ok(Some(serde_json::from_slice(&resp.bytes()?)?))
+}
+
+/// Loads a thread without parsing it, for keeping an exact copy of what the API returned
+pub fn get_thread_json(board: impl AsRef<str>, no: isize, priority: Priority) -> Result<bytes::Bytes, Error> {
+	let board = board.as_ref();
 	let now = Instant::now();
-	
-	let resp = request_url(format!("https://a.4cdn.org/{}/catalog.json", s.clone()));
 
-	info!("Took {:.4} seconds to get /{}/ catalog", now.elapsed().as_secs_f64(), s);
+	let json = fetch(&api_url(format!("{}/thread/{}.json", board, no)), false, &API_LIMITER, priority)?;
+
+	info!("Took {:.4} seconds to get thread /{}/{}", now.elapsed().as_secs_f64(), board, no);
 
-	resp
-		.json::<Vec<CatalogPage>>()
-		.expect("Failed to parse threads list")
-		.remove(0).threads
+	Ok(json)
 }
 
-#[allow(dead_code)]
-pub fn watch_threads(thread_list: Arc<Mutex<Vec<ThreadConfig>>>) -> ! {
-	info!("Watch daemon started with these threads:");
-	for config in (*thread_list).lock().unwrap().iter() {
-		info!(
-			"Thread \"{}\" in board \"{}\" with name \"{}\"",
-			config.id, config.board, config.name
-		);
-	}
-	loop {
-		for mut thread_cfg in (*thread_list).lock().unwrap().iter_mut() {
-			let req = get_client()
-				.get(format!(
-					"https://a.4cdn.org/{}/thread/{}.json",
-					thread_cfg.board, thread_cfg.id
-				))
-				.header(
-					"If-Modified-Since",
-					thread_cfg
-						.last_modified
-						.to_rfc2822()
-						.replace("+0000", "GMT"),
-				)
-				.build()
-				.expect("Failed to build request");
-			let resp = get_client().execute(req).expect("Error requesting page");
-			if let Ok(thread) = resp.json::<Thread>() {
-				for post in thread.posts.iter() {
-					info!("{:#?}", post);
-				}
-			} else {
-				info!("Failed to parse response - assuming empty response body")
-			}
-			thread_cfg.last_modified = Utc::now();
-			// avoid spamming the API
-			thread::sleep(Duration::from_secs(1));
-		}
-		thread::sleep(Duration::from_secs(20));
+/// Downloads a file without going through the cache, for big files that are only needed once
+pub fn download(url: &str, priority: Priority) -> Result<bytes::Bytes, Error> {
+	let req = get_client().get(url).build()?;
+	IMAGE_LIMITER.acquire(priority);
+	Ok(check_status(get_client().execute(req)?)?.bytes()?)
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::{TimeZone, Utc};
+
+	use super::http_date;
+
+	#[test]
+	fn test_http_date() {
+		assert_eq!(http_date(Utc.timestamp(1546275948, 0)), "Mon, 31 Dec 2018 17:05:48 GMT");
 	}
 }
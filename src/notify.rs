@@ -0,0 +1,158 @@
+use std::{io::{self, Write}, process::{Command, Output, Stdio}, thread::{self, JoinHandle}};
+
+use crate::{config::{NotifyConfig, TerminalNotification}, watcher::{EventKind, WatchEvent}};
+
+const TITLE: &str = "fourchan-tui";
+
+/// Environment variables describing an event to the notification command
+pub fn hook_env(event: &WatchEvent) -> Vec<(&'static str, String)> {
+	let post = event.post().map(|post| post.no.to_string()).unwrap_or_default();
+	let reply_to = match event.kind {
+		EventKind::Reply { to, .. } => to.to_string(),
+		_ => String::new(),
+	};
+	vec![
+		("FOURCHAN_EVENT", event.name().to_string()),
+		("FOURCHAN_BOARD", event.board.clone()),
+		("FOURCHAN_THREAD", event.thread.to_string()),
+		("FOURCHAN_POST", post),
+		("FOURCHAN_REPLY_TO", reply_to),
+		("FOURCHAN_EXCERPT", event.excerpt(200).unwrap_or_default()),
+		("FOURCHAN_MESSAGE", event.to_string()),
+	]
+}
+
+/// Runs `command` with `sh -c`, waiting for it to finish.
+/// It's output is captured so it can't end up on the screen, or in the JSON printed by `watch`.
+pub fn run_hook(command: &str, event: &WatchEvent) -> io::Result<Output> {
+	Command::new("sh").arg("-c").arg(command).envs(hook_env(event)).stdin(Stdio::null()).output()
+}
+
+/// Control characters would end the escape sequence early (or start a new one)
+fn sanitize(text: &str) -> String {
+	text.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+}
+
+/// The escape sequence that makes the terminal show a notification, if `kind` is one
+pub fn osc_sequence(kind: TerminalNotification, title: &str, body: &str) -> Option<String> {
+	match kind {
+		TerminalNotification::None => None,
+		// OSC 9 has no separate title
+		TerminalNotification::Osc9 => Some(format!("\x1b]9;{}: {}\x07", sanitize(title), sanitize(body))),
+		// the title of OSC 777 ends at the first `;`
+		TerminalNotification::Osc777 => Some(format!("\x1b]777;notify;{};{}\x07", sanitize(title).replace(';', ","), sanitize(body))),
+	}
+}
+
+/// Announces watcher events as configured in `NotifyConfig`
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+	config: NotifyConfig,
+}
+
+impl Notifier {
+	pub fn new(config: NotifyConfig) -> Notifier {
+		Notifier { config }
+	}
+
+	/// Starts the notification command, if there is one, on a thread of it's own so a slow command doesn't hold up the watcher.
+	/// Returns that thread, which ends once the command has. Failures are logged, they shouldn't stop the watcher.
+	pub fn run_hook(&self, event: &WatchEvent) -> Option<JoinHandle<()>> {
+		let command = self.config.command.clone()?;
+		let event = event.clone();
+		let spawned = thread::Builder::new().name("notify-hook".into()).spawn(move || {
+			match run_hook(&command, &event) {
+				Ok(output) => {
+					for (name, text) in [("output", &output.stdout), ("error output", &output.stderr)] {
+						if !text.is_empty() {
+							log::info!("Notification command {}: {}", name, String::from_utf8_lossy(text).trim_end());
+						}
+					}
+					if !output.status.success() {
+						log::warn!("Notification command exited with {}", output.status);
+					}
+				}
+				Err(e) => log::warn!("Couldn't run the notification command: {}", e),
+			}
+		});
+		spawned.map_err(|e| log::warn!("Couldn't start the notification command: {}", e)).ok()
+	}
+
+	/// The terminal notification for `event`, if those are enabled
+	pub fn terminal_notification(&self, event: &WatchEvent) -> Option<String> {
+		let body = match event.excerpt(100) {
+			Some(excerpt) => format!("{}: {}", event, excerpt),
+			None => event.to_string(),
+		};
+		osc_sequence(self.config.terminal, TITLE, &body)
+	}
+
+	/// Starts the hook and writes the terminal notification (if any) to `terminal`.
+	/// Returns the thread the hook runs on, like `run_hook`.
+	pub fn notify(&self, event: &WatchEvent, terminal: &mut impl Write) -> Option<JoinHandle<()>> {
+		let hook = self.run_hook(event);
+		if let Some(sequence) = self.terminal_notification(event) {
+			if let Err(e) = terminal.write_all(sequence.as_bytes()).and_then(|_| terminal.flush()) {
+				log::warn!("Couldn't show a terminal notification: {}", e);
+			}
+		}
+		hook
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::{Notifier, osc_sequence};
+	use crate::{config::{NotifyConfig, TerminalNotification}, data::Thread, watcher::{EventKind, WatchEvent}};
+
+	fn reply_event() -> WatchEvent {
+		let test = fs::read_to_string("assets/test/dummy.json").unwrap();
+		let thread: Thread = serde_json::de::from_str(&test).unwrap();
+		WatchEvent {
+			board: "po".into(),
+			thread: 570368,
+			kind: EventKind::Reply { post: thread.posts[1].clone(), to: 570368 },
+		}
+	}
+
+	#[test]
+	fn test_hook_script() {
+		let dir = std::env::temp_dir().join(format!("fourchan-tui-notify-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let out = dir.join("events.txt");
+		let script = dir.join("hook.sh");
+		fs::write(&script, format!(
+			"echo \"$FOURCHAN_EVENT $FOURCHAN_BOARD $FOURCHAN_THREAD $FOURCHAN_POST $FOURCHAN_REPLY_TO\" >> '{}'\n\
+			echo \"$FOURCHAN_EXCERPT\" >> '{}'\n\
+			echo 'not on the terminal'\n",
+			out.display(), out.display()
+		)).unwrap();
+
+		let notifier = Notifier::new(NotifyConfig { command: Some(format!("sh '{}'", script.display())), terminal: TerminalNotification::Osc777 });
+		let mut terminal = Vec::new();
+		// the hooks run in the background, in order only because each is waited for
+		notifier.notify(&reply_event(), &mut terminal).unwrap().join().unwrap();
+		notifier.notify(&WatchEvent { board: "po".into(), thread: 570368, kind: EventKind::NotFound }, &mut terminal).unwrap().join().unwrap();
+
+		let written = fs::read_to_string(&out).unwrap();
+		let lines: Vec<&str> = written.lines().collect();
+		assert_eq!(lines[0], "reply po 570368 570370 570368");
+		assert!(lines[1].starts_with("FAQs about papercraft What paper should I use?"));
+		assert_eq!(lines[2], "not_found po 570368  ");
+		assert_eq!(lines[3], "");
+
+		let terminal = String::from_utf8(terminal).unwrap();
+		assert!(terminal.starts_with("\x1b]777;notify;fourchan-tui;>>570370 replied to your post >>570368 in /po/570368: FAQs"));
+		assert!(terminal.ends_with("\x1b]777;notify;fourchan-tui;/po/570368 404'd\x07"));
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn test_osc_sequences() {
+		assert_eq!(osc_sequence(TerminalNotification::None, "t", "b"), None);
+		assert_eq!(osc_sequence(TerminalNotification::Osc9, "t", "new\npost\x07").unwrap(), "\x1b]9;t: new post \x07");
+		assert_eq!(osc_sequence(TerminalNotification::Osc777, "a;b", "c;d").unwrap(), "\x1b]777;notify;a,b;c;d\x07");
+	}
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use crate::{data::Thread, html::{self, Tag, Token}};
+
+/// Where a `>>` quotelink in a comment points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuoteLink {
+	// `>>123`, a post in the same thread
+	Post(isize),
+	// `>>123` or `>>>/g/123`, a post in another thread (possibly on another board)
+	CrossThread { board: String, thread: isize, post: isize },
+	// `>>>/po/` or `>>>/po/lft`, a board or a catalog search rather than a specific post
+	Board { board: String, search: Option<String> },
+}
+
+impl QuoteLink {
+	/// Parses the href of an `<a class="quotelink">`.
+	/// Links to a post in `thread` on `board` are always treated as same-thread links.
+	pub fn from_href(href: &str, board: &str, thread: isize) -> Option<QuoteLink> {
+		if let Some(post) = href.strip_prefix("#p") {
+			return post.parse().ok().map(QuoteLink::Post);
+		}
+		// drop the scheme and host from absolute links like //boards.4channel.org/po/catalog#s=lft
+		let path = match href.find("//") {
+			Some(i) => {
+				let after_host = &href[i + 2..];
+				&after_host[after_host.find('/')?..]
+			}
+			None => href,
+		};
+		let (path, fragment) = match path.find('#') {
+			Some(i) => (&path[..i], Some(&path[i + 1..])),
+			None => (path, None),
+		};
+		let mut segments = path.split('/').filter(|s| !s.is_empty());
+		let link_board = segments.next()?.to_string();
+		match (segments.next(), segments.next()) {
+			(Some("thread"), Some(link_thread)) => {
+				let link_thread: isize = link_thread.parse().ok()?;
+				let post = match fragment.and_then(|f| f.strip_prefix('p')) {
+					Some(post) => post.parse().ok()?,
+					None => link_thread,
+				};
+				if link_board == board && link_thread == thread {
+					Some(QuoteLink::Post(post))
+				} else {
+					Some(QuoteLink::CrossThread { board: link_board, thread: link_thread, post })
+				}
+			}
+			_ => Some(QuoteLink::Board {
+				board: link_board,
+				search: fragment.and_then(|f| f.strip_prefix("s=")).map(str::to_string),
+			}),
+		}
+	}
+}
+
+/// Returns every quotelink in a comment, in the order they appear
+pub fn parse_quotelinks(com: &str, board: &str, thread: isize) -> Vec<QuoteLink> {
+	html::tokenize(com)
+		.into_iter()
+		.filter_map(|token| match token {
+			Token::Open { tag: Tag::QuoteLink(href), .. } => QuoteLink::from_href(&href, board, thread),
+			_ => None,
+		})
+		.collect()
+}
+
+/// The quote graph of a thread: which posts each post quotes, and which posts reply to it
+#[derive(Debug, Default)]
+pub struct ThreadIndex {
+	quotes:  HashMap<isize, Vec<QuoteLink>>,
+	replies: HashMap<isize, Vec<isize>>,
+}
+
+impl ThreadIndex {
+	pub fn new(board: &str, thread: &Thread) -> ThreadIndex {
+		let mut index = ThreadIndex::default();
+		let thread_no = match thread.posts.first() {
+			Some(op) => op.no,
+			None => return index,
+		};
+		for post in thread.posts.iter() {
+			let links = parse_quotelinks(post.com.as_deref().unwrap_or(""), board, thread_no);
+			for link in links.iter() {
+				if let QuoteLink::Post(target) = link {
+					let replies = index.replies.entry(*target).or_default();
+					// quoting the same post twice is still only one reply
+					if !replies.contains(&post.no) {
+						replies.push(post.no);
+					}
+				}
+			}
+			if !links.is_empty() {
+				index.quotes.insert(post.no, links);
+			}
+		}
+		index
+	}
+
+	/// The quotelinks contained in post `no`
+	#[allow(dead_code)]
+	pub fn quotes(&self, no: isize) -> &[QuoteLink] {
+		self.quotes.get(&no).map_or(&[], Vec::as_slice)
+	}
+
+	/// The posts in this thread that quote post `no`, in thread order
+	pub fn replies(&self, no: isize) -> &[isize] {
+		self.replies.get(&no).map_or(&[], Vec::as_slice)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{QuoteLink, ThreadIndex, parse_quotelinks};
+	use crate::data::Thread;
+
+	fn load_dummy() -> Thread {
+		let test = std::fs::read_to_string("assets/test/dummy.json").unwrap();
+		serde_json::de::from_str(&test).unwrap()
+	}
+
+	#[test]
+	fn test_from_href() {
+		assert_eq!(QuoteLink::from_href("#p570370", "po", 570368), Some(QuoteLink::Post(570370)));
+		assert_eq!(QuoteLink::from_href("/po/thread/570368#p570371", "po", 570368), Some(QuoteLink::Post(570371)));
+		assert_eq!(
+			QuoteLink::from_href("/po/thread/123#p456", "po", 570368),
+			Some(QuoteLink::CrossThread { board: "po".into(), thread: 123, post: 456 })
+		);
+		assert_eq!(
+			QuoteLink::from_href("/g/thread/123", "po", 570368),
+			Some(QuoteLink::CrossThread { board: "g".into(), thread: 123, post: 123 })
+		);
+		assert_eq!(
+			QuoteLink::from_href("//boards.4channel.org/po/catalog#s=lft", "po", 570368),
+			Some(QuoteLink::Board { board: "po".into(), search: Some("lft".into()) })
+		);
+		assert_eq!(
+			QuoteLink::from_href("//boards.4channel.org/wsr/", "g", 1),
+			Some(QuoteLink::Board { board: "wsr".into(), search: None })
+		);
+		assert_eq!(QuoteLink::from_href("#pgarbage", "po", 1), None);
+	}
+
+	#[test]
+	fn test_dummy_cross_board() {
+		let thread = load_dummy();
+		let links = parse_quotelinks(thread.posts[2].com.as_ref().unwrap(), "po", 570368);
+		assert_eq!(links, vec![QuoteLink::Board { board: "po".into(), search: Some("lft".into()) }]);
+		let index = ThreadIndex::new("po", &thread);
+		assert!(index.replies(570368).is_empty());
+		assert_eq!(index.quotes(570371).len(), 1);
+	}
+
+	#[test]
+	fn test_backlinks() {
+		let mut thread = load_dummy();
+		thread.posts[1].com = Some("<a href=\"#p570368\" class=\"quotelink\">&gt;&gt;570368</a><br>welcome".into());
+		thread.posts[2].com = Some(
+			"<a href=\"#p570368\" class=\"quotelink\">&gt;&gt;570368</a>\
+			<a href=\"#p570370\" class=\"quotelink\">&gt;&gt;570370</a>\
+			<a href=\"#p570370\" class=\"quotelink\">&gt;&gt;570370</a>\
+			<a href=\"/g/thread/1#p2\" class=\"quotelink\">&gt;&gt;&gt;/g/2</a>".into()
+		);
+		let index = ThreadIndex::new("po", &thread);
+		assert_eq!(index.replies(570368), &[570370, 570371]);
+		assert_eq!(index.replies(570370), &[570371]);
+		assert!(index.replies(570371).is_empty());
+		assert_eq!(index.quotes(570371).len(), 4);
+		assert_eq!(index.quotes(570371)[3], QuoteLink::CrossThread { board: "g".into(), thread: 1, post: 2 });
+	}
+}
@@ -0,0 +1,153 @@
+use std::{sync::{Condvar, Mutex}, time::{Duration, Instant}};
+
+/// How urgent a request is. Waiting `User` requests are always let through before `Background` ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+	// something the user is looking at right now
+	User,
+	// refreshes nobody is actively waiting for, like the thread watcher
+	Background,
+}
+
+impl Priority {
+	fn queue(self) -> usize {
+		match self {
+			Priority::User => 0,
+			Priority::Background => 1,
+		}
+	}
+}
+
+#[derive(Debug)]
+struct State {
+	// earliest time the next request may be sent, `None` before the first one
+	next_slot: Option<Instant>,
+	// ticket dispensers for each priority, waiters are served in the order they arrived
+	next_ticket: [u64; 2],
+	serving:     [u64; 2],
+}
+
+/// What a waiting request has to do next
+#[derive(Debug, PartialEq, Eq)]
+enum Poll {
+	// another request goes first
+	Queued,
+	// it's next, but the previous request was too recent
+	Until(Instant),
+	// it may be sent now
+	Ready,
+}
+
+impl State {
+	const fn new() -> State {
+		State { next_slot: None, next_ticket: [0; 2], serving: [0; 2] }
+	}
+
+	fn take_ticket(&mut self, priority: Priority) -> u64 {
+		let queue = priority.queue();
+		let ticket = self.next_ticket[queue];
+		self.next_ticket[queue] += 1;
+		ticket
+	}
+
+	/// Checks whether the request holding `ticket` may be sent at `now`, and takes the slot if so
+	fn poll(&mut self, priority: Priority, ticket: u64, interval: Duration, now: Instant) -> Poll {
+		let queue = priority.queue();
+		let users_waiting = self.serving[0] != self.next_ticket[0];
+		let our_turn = self.serving[queue] == ticket && (priority == Priority::User || !users_waiting);
+		match self.next_slot {
+			_ if !our_turn => Poll::Queued,
+			Some(slot) if slot > now => Poll::Until(slot),
+			_ => {
+				self.next_slot = Some(now + interval);
+				self.serving[queue] += 1;
+				Poll::Ready
+			}
+		}
+	}
+}
+
+/// Spaces out requests so at most one is sent every `interval`, across all threads
+#[derive(Debug)]
+pub struct RateLimiter {
+	interval: Duration,
+	state:    Mutex<State>,
+	turn:     Condvar,
+}
+
+impl RateLimiter {
+	pub const fn new(interval: Duration) -> RateLimiter {
+		RateLimiter { interval, state: Mutex::new(State::new()), turn: Condvar::new() }
+	}
+
+	/// Blocks until a request with the given priority may be sent
+	pub fn acquire(&self, priority: Priority) {
+		let mut state = self.state.lock().unwrap();
+		let ticket = state.take_ticket(priority);
+		loop {
+			let now = Instant::now();
+			match state.poll(priority, ticket, self.interval, now) {
+				Poll::Queued => state = self.turn.wait(state).unwrap(),
+				Poll::Until(slot) => state = self.turn.wait_timeout(state, slot - now).unwrap().0,
+				Poll::Ready => {
+					self.turn.notify_all();
+					return;
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use super::{Poll, Priority, State};
+
+	const INTERVAL: Duration = Duration::from_millis(20);
+
+	#[test]
+	fn test_spacing() {
+		let mut state = State::new();
+		let start = Instant::now();
+		let first = state.take_ticket(Priority::User);
+		assert_eq!(state.poll(Priority::User, first, INTERVAL, start), Poll::Ready);
+
+		let second = state.take_ticket(Priority::User);
+		let slot = start + INTERVAL;
+		assert_eq!(state.poll(Priority::User, second, INTERVAL, start + Duration::from_millis(5)), Poll::Until(slot));
+		assert_eq!(state.poll(Priority::User, second, INTERVAL, slot), Poll::Ready);
+
+		// a request long after the last one goes through right away
+		let third = state.take_ticket(Priority::User);
+		assert_eq!(state.poll(Priority::User, third, INTERVAL, slot + INTERVAL * 10), Poll::Ready);
+	}
+
+	#[test]
+	fn test_user_before_background() {
+		let mut state = State::new();
+		let start = Instant::now();
+		let first = state.take_ticket(Priority::User);
+		assert_eq!(state.poll(Priority::User, first, INTERVAL, start), Poll::Ready);
+
+		// the background request is queued first, but the user request gets the next slot
+		let background = state.take_ticket(Priority::Background);
+		let user = state.take_ticket(Priority::User);
+		let slot = start + INTERVAL;
+		assert_eq!(state.poll(Priority::Background, background, INTERVAL, slot), Poll::Queued);
+		assert_eq!(state.poll(Priority::User, user, INTERVAL, slot), Poll::Ready);
+		assert_eq!(state.poll(Priority::Background, background, INTERVAL, slot), Poll::Until(slot + INTERVAL));
+		assert_eq!(state.poll(Priority::Background, background, INTERVAL, slot + INTERVAL), Poll::Ready);
+	}
+
+	#[test]
+	fn test_tickets_in_order() {
+		let mut state = State::new();
+		let start = Instant::now();
+		let first = state.take_ticket(Priority::Background);
+		let second = state.take_ticket(Priority::Background);
+		assert_eq!(state.poll(Priority::Background, second, INTERVAL, start), Poll::Queued);
+		assert_eq!(state.poll(Priority::Background, first, INTERVAL, start), Poll::Ready);
+		assert_eq!(state.poll(Priority::Background, second, INTERVAL, start + INTERVAL), Poll::Ready);
+	}
+}
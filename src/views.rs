@@ -1,14 +1,24 @@
-use std::{convert::TryInto};
+use std::{convert::TryInto, error::Error, panic::{self, AssertUnwindSafe}, rc::Rc, sync::{Arc, Mutex, OnceLock}};
 
-use string_builder::Builder as StringBuilder;
 use wasmer_enumset::EnumSet;
-use image::{DynamicImage, GenericImageView, ImageBuffer, Luma, Pixel, Rgba};
-use cursive::{Vec2, View, direction::Orientation, theme::{Color, ColorStyle, ColorType, Style}, utils::markup::StyledString};
+use image::{DynamicImage, GenericImageView, ImageBuffer, ImageResult, Luma, Pixel, RgbImage, Rgba, imageops::FilterType};
+use cursive::{CbSink, Cursive, Vec2, View, direction::{Direction, Orientation}, event::{Event, EventResult, Key}, theme::{ColorStyle, ColorType, Effect, Style}, utils::markup::StyledString};
+
+use self::{blocks::Glyphs, graphics::{Graphic, kitty::KittyImage}, viewer::Viewport};
 
 pub use self::data::*;
+pub use self::comment::CommentView;
+pub use self::viewer::ImageViewer;
 
 
 pub mod traits;
+pub mod graphics;
+mod blocks;
+mod braille;
+mod comment;
+mod dither;
+pub mod palette;
+mod viewer;
 
 mod data {
 	use std::{convert::TryInto, error::Error, fmt::Display};
@@ -16,10 +26,17 @@ mod data {
 	use cursive::utils::markup::StyledString;
 	use image::imageops::FilterType;
 
+	use super::graphics::Graphic;
+
 	#[derive(Debug)]
 	pub enum ImageRenderable {
 		Styled(Vec<StyledString>),
 		Raw(Vec<String>),
+		// the image for a terminal graphics protocol, and what to show instead when it can't be placed
+		Graphics {
+			fallback: Vec<StyledString>,
+			image:    Graphic,
+		},
 		// Gui(Window)
 	}
 	
@@ -27,10 +44,36 @@ mod data {
 	pub enum RenderMode {
 		// full 24-bit color
 		Color,
+		// 2×2 or 2×3 pixels per cell, in the two colors that fit them best
+		Quadrant,
+		Sextant,
 		Grayscale,
+		// sixel graphics, falling back to `Color` where they can't be shown
+		Sixel,
+		// the kitty graphics protocol, with the same fallback
+		Kitty,
+		// iTerm2's inline images, also supported by WezTerm, with the same fallback
+		ITermInline,
+		// 2×4 dots per cell, in the terminal's foreground color or colored per cell
+		Braille,
+		BrailleColor,
 		Gui,
 	}
 	
+	/// How pixels are reduced to what the terminal can show: the few brightness levels of shade and Braille characters,
+	/// or the 256 or 16 colors of terminals without 24-bit color. 24-bit color is shown as it is.
+	#[derive(Debug, Clone, Copy)]
+	pub enum Dithering {
+		// every pixel is rounded on it's own
+		None,
+		// a fixed 4×4 pattern of thresholds
+		Bayer,
+		// the rounding error is spread to the neighbouring pixels
+		FloydSteinberg,
+		// like Floyd-Steinberg, but only 3/4 of the error is spread
+		Atkinson,
+	}
+	
 	#[derive(Debug, Clone, Copy)]
 	pub enum ConversionError {
 		InvalidMode
@@ -72,51 +115,275 @@ mod data {
 	
 }
 
+/// A pool of worker threads that images are downloaded and rendered on
+mod pool {
+	use std::{panic::{self, AssertUnwindSafe}, sync::{Arc, Mutex, OnceLock, mpsc::{self, Sender}}, thread};
+
+	type Job = Box<dyn FnOnce() + Send>;
+
+	static POOL: OnceLock<Mutex<Sender<Job>>> = OnceLock::new();
+
+	fn start() -> Mutex<Sender<Job>> {
+		let (sender, receiver) = mpsc::channel::<Job>();
+		let receiver = Arc::new(Mutex::new(receiver));
+		let workers = thread::available_parallelism().map_or(4, |n| n.get()).clamp(2, 8);
+		for i in 0..workers {
+			let receiver = receiver.clone();
+			thread::Builder::new()
+				.name(format!("image-worker-{}", i))
+				.spawn(move || loop {
+					// the lock is only held while waiting for a job, not while running it
+					let job = receiver.lock().unwrap().recv();
+					match job {
+						// a panicking job would otherwise take the worker down with it
+						Ok(job) => {
+							let _ = panic::catch_unwind(AssertUnwindSafe(job));
+						}
+						Err(_) => return,
+					}
+				})
+				.expect("Failed to spawn image worker");
+		}
+		Mutex::new(sender)
+	}
+
+	/// Runs `job` on one of the image workers
+	pub fn spawn(job: impl FnOnce() + Send + 'static) {
+		let _ = POOL.get_or_init(start).lock().unwrap().send(Box::new(job));
+	}
+}
+
 #[derive(Debug)]
-pub struct ImageView {
+struct LoadedImage {
 	rendered: ImageRenderable,
 	size: Vec2,
 }
 
+type SubmitCallback = Rc<dyn Fn(&mut Cursive)>;
+
+/// A full size image that is downloaded and decoded by the first render that needs it,
+/// and then kept around to be rendered again at other zoom levels
+#[derive(Debug)]
+struct ImageSource {
+	url: String,
+	// `None` if it failed to load
+	decoded: OnceLock<Option<DynamicImage>>,
+}
+
+impl ImageSource {
+	fn new(url: impl Into<String>) -> ImageSource {
+		ImageSource { url: url.into(), decoded: OnceLock::new() }
+	}
+
+	/// Blocks until the image is decoded, or another thread is done decoding it
+	fn decoded(&self) -> Option<&DynamicImage> {
+		self.decoded
+			.get_or_init(|| fetch_image(&self.url).map_err(|e| log::warn!("Failed to load image {:?}: {}", self.url, e)).ok())
+			.as_ref()
+	}
+}
+
+pub struct ImageView {
+	// `None` while the image is still being loaded in the background
+	rendered: Arc<Mutex<Option<LoadedImage>>>,
+	dims: Vec2,
+	on_submit: Option<SubmitCallback>,
+}
+
 impl ImageView {
-	pub fn new<'a>(url: impl AsRef<str> + Clone + std::fmt::Debug, dims: impl Into<Vec2>, render_mode: RenderMode, scale_method: ScaleMode) -> ImageView {
+	/// Downloads and renders an image, blocking until it's done
+	#[allow(dead_code)] // only used by the benchmarks since thumbnails are loaded in the background
+	pub fn new<'a>(url: impl AsRef<str> + Clone + std::fmt::Debug, dims: impl Into<Vec2>, render_mode: RenderMode, scale_method: ScaleMode, dithering: Dithering) -> ImageView {
 		let dims = dims.into();
-		let bytes = crate::net::get_bytes(url.clone());
-		
-		let img = decode_image(bytes.as_ref());
+		ImageView {
+			rendered: Arc::new(Mutex::new(Some(Self::load_image(url, dims, render_mode, scale_method, dithering)))),
+			dims,
+			on_submit: None,
+		}
+		// img.get_pixel(0, 0);
+	}
+
+	/// Creates a view that shows a placeholder until the image has been downloaded and rendered on a worker thread.
+	/// `cb_sink` is used to redraw the UI once it's ready.
+	pub fn load(url: impl Into<String>, dims: impl Into<Vec2>, render_mode: RenderMode, scale_method: ScaleMode, dithering: Dithering, cb_sink: CbSink) -> ImageView {
+		let url = url.into();
+		let dims = dims.into();
+		Self::spawn(dims, cb_sink, move || Self::load_image(url, dims, render_mode, scale_method, dithering))
+	}
+
+	/// Like `load`, but only renders the part of `source` that `viewport` shows
+	fn load_viewport(source: Arc<ImageSource>, viewport: Viewport, dims: Vec2, render_mode: RenderMode, scale_method: ScaleMode, dithering: Dithering, cb_sink: CbSink) -> ImageView {
+		Self::spawn(dims, cb_sink, move || match source.decoded() {
+			Some(img) => {
+				let (x, y, width, height) = viewport.crop(img.dimensions(), dims);
+				Self::render(&img.crop_imm(x, y, width, height), dims, render_mode, scale_method, dithering)
+			}
+			None => Self::placeholder(dims, "[image failed to load]"),
+		})
+	}
+
+	fn spawn(dims: Vec2, cb_sink: CbSink, load: impl FnOnce() -> LoadedImage + Send + 'static) -> ImageView {
+		let rendered = Arc::new(Mutex::new(None));
+		let weak = Arc::downgrade(&rendered);
+		pool::spawn(move || {
+			// the view may have been thrown away (e.g. another board was opened) before we got to it
+			if weak.strong_count() == 0 {
+				return;
+			}
+			// a bad image shouldn't leave the view loading forever
+			let loaded = panic::catch_unwind(AssertUnwindSafe(load)).unwrap_or_else(|_| {
+				log::warn!("Rendering an image panicked");
+				Self::placeholder(dims, "[image failed to render]")
+			});
+			if let Some(rendered) = weak.upgrade() {
+				*rendered.lock().unwrap() = Some(loaded);
+				// an empty callback is enough to wake up the event loop and redraw
+				let _ = cb_sink.send(Box::new(|_| {}));
+			}
+		});
+		ImageView { rendered, dims, on_submit: None }
+	}
+
+	/// Sets the callback run when Enter is pressed on the image, which makes it focusable
+	pub fn on_submit<F>(mut self, cb: F) -> Self
+	where
+		F: Fn(&mut Cursive) + 'static,
+	{
+		self.on_submit = Some(Rc::new(cb));
+		self
+	}
+
+	/// Whether the image is done loading, or failed to
+	pub fn is_loaded(&self) -> bool {
+		self.rendered.lock().unwrap().is_some()
+	}
+
+	fn load_image(url: impl AsRef<str> + std::fmt::Debug, dims: Vec2, render_mode: RenderMode, scale_method: ScaleMode, dithering: Dithering) -> LoadedImage {
+		match fetch_image(url.as_ref()) {
+			Ok(img) => Self::render(&img, dims, render_mode, scale_method, dithering),
+			Err(e) => {
+				log::warn!("Failed to load image {:?}: {}", url, e);
+				Self::placeholder(dims, "[image failed to load]")
+			}
+		}
+	}
+
+	fn render(img: &DynamicImage, dims: Vec2, render_mode: RenderMode, scale_method: ScaleMode, dithering: Dithering) -> LoadedImage {
 		let (size, rendered) = match render_mode {
 		    RenderMode::Color => {
-				let styled = Self::img_to_color_unicode(&img, dims, scale_method);
-				(Vec2::new(styled[0].width(), styled.len()), ImageRenderable::Styled(styled))
+				let styled = Self::img_to_color_unicode(img, dims, scale_method, dithering);
+				(Vec2::new(styled.first().map_or(0, |line| line.width()), styled.len()), ImageRenderable::Styled(styled))
 			}
 		    RenderMode::Grayscale => {
-				let gray = Self::img_to_gray_unicode(img, dims, scale_method);
-				(Vec2::new(gray[0].len(), gray.len()), ImageRenderable::Raw(gray))
+				let gray = Self::img_to_gray_unicode(img, dims, scale_method, dithering);
+				(Vec2::new(gray.first().map_or(0, |line| line.chars().count()), gray.len()), ImageRenderable::Raw(gray))
+			}
+		    RenderMode::Sixel | RenderMode::Kitty | RenderMode::ITermInline => {
+				let fallback = Self::img_to_color_unicode(img, dims, scale_method, dithering);
+				let size = Vec2::new(fallback.first().map_or(0, |line| line.width()), fallback.len());
+				// there's nothing to place a graphic in if the image is less than a cell tall
+				let image = graphics::cell_size()
+					.filter(|_| size.x > 0 && size.y > 0)
+					.and_then(|cell| Self::img_to_graphic(img, size, cell, render_mode, scale_method));
+				match image {
+					Some(image) => (size, ImageRenderable::Graphics { fallback, image }),
+					None => (size, ImageRenderable::Styled(fallback)),
+				}
+			}
+		    RenderMode::Quadrant | RenderMode::Sextant => {
+				let glyphs = match render_mode {
+					RenderMode::Quadrant => Glyphs::Quadrants,
+					_ => Glyphs::Sextants,
+				};
+				let styled = Self::img_to_block_unicode(img, dims, scale_method, glyphs, dithering);
+				(Vec2::new(styled.first().map_or(0, |line| line.width()), styled.len()), ImageRenderable::Styled(styled))
+			}
+		    RenderMode::Braille => {
+				let lines = braille::render(&Self::resize_for_braille(img, dims, scale_method), dithering);
+				(Vec2::new(lines.first().map_or(0, |line| line.chars().count()), lines.len()), ImageRenderable::Raw(lines))
+			}
+		    RenderMode::BrailleColor => {
+				let styled = braille::render_color(&Self::resize_for_braille(img, dims, scale_method), dithering, palette::color_depth());
+				(Vec2::new(styled.first().map_or(0, |line| line.width()), styled.len()), ImageRenderable::Styled(styled))
 			}
 		    RenderMode::Gui => {todo!()}
 		};
-		ImageView {
+		LoadedImage {
 			rendered,
 			size,
 		}
-		// img.get_pixel(0, 0);
+	}
+
+	/// Encodes the image for a graphics protocol, at the resolution of `size` cells of `cell` pixels.
+	/// Returns `None` if the terminal doesn't support the protocol.
+	fn img_to_graphic(img: &DynamicImage, size: Vec2, cell: Vec2, render_mode: RenderMode, scale_method: ScaleMode) -> Option<Graphic> {
+		let filter = scale_method.try_into().unwrap_or(FilterType::Nearest);
+		let (width, height) = ((size.x * cell.x) as u32, (size.y * cell.y) as u32);
+		match render_mode {
+			RenderMode::Sixel if graphics::supports_sixel() => {
+				// sixels are drawn in bands of 6 pixels, a band sticking out of the last row would cover the row below
+				let pixels = img.resize(width, height / 6 * 6, filter).into_rgb8();
+				Some(Graphic::Inline(Arc::new(graphics::sixel::encode(&pixels))))
+			}
+			RenderMode::Kitty if graphics::supports_kitty() => {
+				Self::img_to_png(img, width, height, filter).map(|png| Graphic::Kitty(Arc::new(KittyImage::new(png))))
+			}
+			RenderMode::ITermInline if graphics::supports_iterm() => {
+				Self::img_to_png(img, width, height, filter)
+					.map(|png| Graphic::Inline(Arc::new(graphics::iterm::encode(&png, size.x, size.y))))
+			}
+			_ => None,
+		}
+	}
+
+	fn img_to_block_unicode(img: &DynamicImage, dims: Vec2, scale_method: ScaleMode, glyphs: Glyphs, dithering: Dithering) -> Vec<StyledString> {
+		let (cell_width, cell_height) = glyphs.cell_size();
+		let filter = scale_method.try_into().unwrap_or(FilterType::Nearest);
+		// the pixels of a block glyph are taller than they're wide, so the size is worked out in braille dots
+		// (2×4 to a cell, and about square) and then squashed down to the rows the glyphs have
+		let (width, height) = img.dimensions();
+		let scale = f64::min((dims.x * 2) as f64 / width as f64, (dims.y * 4) as f64 / height as f64);
+		let width = ((width as f64 * scale).round() as u32).clamp(1, (dims.x * cell_width) as u32);
+		let height = ((height as f64 * scale * cell_height as f64 / 4.0).round() as u32).clamp(cell_height as u32, (dims.y * cell_height) as u32);
+		blocks::render(&img.resize_exact(width, height, filter).into_rgb8(), glyphs, dithering, palette::color_depth())
+	}
+
+	fn resize_for_braille(img: &DynamicImage, dims: Vec2, scale_method: ScaleMode) -> RgbImage {
+		img.resize(dims.x as u32 * 2, dims.y as u32 * 4, scale_method.try_into().unwrap_or(FilterType::Nearest)).into_rgb8()
+	}
+
+	fn img_to_png(img: &DynamicImage, width: u32, height: u32, filter: FilterType) -> Option<Vec<u8>> {
+		graphics::encode_png(&img.resize(width, height, filter).into_rgba8())
+			.map_err(|e| log::warn!("Failed to encode an image as PNG: {}", e))
+			.ok()
+	}
+
+	/// An image-sized box showing `text` in place of the image
+	fn placeholder(dims: Vec2, text: &str) -> LoadedImage {
+		let mut lines = vec![" ".repeat(dims.x); dims.y.max(1)];
+		lines[0] = format!("{:<width$.width$}", text, width = dims.x);
+		LoadedImage {
+			size: Vec2::new(dims.x, lines.len()),
+			rendered: ImageRenderable::Raw(lines),
+		}
 	}
 	
-	fn img_to_color_unicode(img: &DynamicImage, dims: Vec2, scale_method: ScaleMode) -> Vec<StyledString> {
-		let resized = img.resize(dims.x as u32, dims.y as u32 * 2,scale_method.try_into().unwrap());
+	fn img_to_color_unicode(img: &DynamicImage, dims: Vec2, scale_method: ScaleMode, dithering: Dithering) -> Vec<StyledString> {
+		// Fast Nearest is only implemented for grayscale, so fall back to the closest filter
+		let resized = img.resize(dims.x as u32, dims.y as u32 * 2, scale_method.try_into().unwrap_or(FilterType::Nearest)).into_rgb8();
+		let colors = palette::quantize(&resized, palette::color_depth(), dithering);
+		let width = resized.width() as usize;
 		let mut output = Vec::new();
-		for y in 0..resized.height()/2 {
+		for y in 0..resized.height() as usize / 2 {
 			let mut builder = StyledString::new();
-			for x in 0..resized.width() {
-				let top_pixel = resized.get_pixel(x, y * 2);
-				let top_chnls = top_pixel.channels();
-				let bottom_pixel = resized.get_pixel(x, y * 2 + 1);
-				let bottom_chnls = bottom_pixel.channels();
+			for x in 0..width {
+				let top_color = colors[y * 2 * width + x];
+				let bottom_color = colors[(y * 2 + 1) * width + x];
 				let style = Style{
 					effects: EnumSet::new(),
 					color: ColorStyle::new(
-					ColorType::Color(Color::Rgb(bottom_chnls[0], bottom_chnls[1], bottom_chnls[2])), 
-					ColorType::Color(Color::Rgb(top_chnls[0], top_chnls[1], top_chnls[2]))
+					ColorType::Color(bottom_color), 
+					ColorType::Color(top_color)
 				)};
 				builder.append_styled('▄', style);
 			}
@@ -125,29 +392,14 @@ impl ImageView {
 		output
 	}
 
-	fn resized_i2g_unicode(img: &ImageBuffer<Luma<u8>, Vec<u8>>, dims: Vec2) -> Vec<String> {
-		let mut output = Vec::new();
-		for y in 0..img.height()/2 {
-			let mut builder = StringBuilder::new(dims.x);
-			for x in 0..img.width() {
-				let top_pixel = img.get_pixel(x, y * 2);
-				let top_luma = top_pixel.channels()[0] as usize;
-				let bottom_pixel = img.get_pixel(x, y * 2 + 1);
-				let bottom_luma = bottom_pixel.channels()[0] as usize;
-				let luma = (top_luma + bottom_luma) / 2;
-				let luma_char = match luma {
-					0..=51 => ' ',
-					52..=102 => '░',
-					103..=153 => '▒',
-					154..=204 => '▓',
-					205..=256 => '█',
-					_ => panic!("invalid luma")
-				};
-				builder.append(luma_char);
-			}
-			output.push(builder.string().unwrap())
-		}
-		output
+	fn resized_i2g_unicode(img: &ImageBuffer<Luma<u8>, Vec<u8>>, dithering: Dithering) -> Vec<String> {
+		// every cell shows the average of two pixels
+		let luma = ImageBuffer::from_fn(img.width(), img.height() / 2, |x, y| {
+			let top_luma = img.get_pixel(x, y * 2).channels()[0] as usize;
+			let bottom_luma = img.get_pixel(x, y * 2 + 1).channels()[0] as usize;
+			Luma([((top_luma + bottom_luma) / 2) as u8])
+		});
+		Self::luma_to_shades(&luma, dithering)
 	}
 	
 	fn rgb_to_luma(rgb: Rgba<u8>) -> usize {
@@ -155,62 +407,103 @@ impl ImageView {
 		(0.2126 * channels[0] as f64 + 0.7152 * channels[1] as f64 + 0.0722 * channels[2] as f64) as usize
 	}
 
-	fn n64_i2g_unicode(img: &DynamicImage, dims: Vec2) -> Vec<String> {
+	fn n64_i2g_unicode(img: &DynamicImage, dims: Vec2, dithering: Dithering) -> Vec<String> {
 		let scale_x = img.width() as f64 / dims.x as f64;
 		let scale_y = img.height() as f64 / dims.y as f64;
-		let mut output = Vec::new();
-		for y in 0..dims.y/2 {
-			let mut builder = StringBuilder::new(dims.x);
+		let luma = ImageBuffer::from_fn(dims.x as u32, dims.y as u32 / 2, |x, y| {
 			let scaled_top_y = ((y * 2) as f64 * scale_y) as u32;
 			let scaled_bottom_y = ((y * 2 + 1) as f64 * scale_y) as u32;
-			for x in 0..dims.x {
-				let scaled_x = (x as f64 * scale_x) as u32;
-				let top_luma = Self::rgb_to_luma(img.get_pixel(scaled_x, scaled_top_y));
-				let bottom_luma = Self::rgb_to_luma(img.get_pixel(scaled_x, scaled_bottom_y));
-				let luma = (top_luma + bottom_luma) / 2;
-				let luma_char = match luma {
-					0..=51 => ' ',
-					52..=102 => '░',
-					103..=153 => '▒',
-					154..=204 => '▓',
-					205..=256 => '█',
-					_ => panic!("invalid luma")
-				};
-				builder.append(luma_char);
-			}
-			output.push(builder.string().unwrap())
-		}
-		output
+			let scaled_x = (x as f64 * scale_x) as u32;
+			let top_luma = Self::rgb_to_luma(img.get_pixel(scaled_x, scaled_top_y));
+			let bottom_luma = Self::rgb_to_luma(img.get_pixel(scaled_x, scaled_bottom_y));
+			Luma([((top_luma + bottom_luma) / 2) as u8])
+		});
+		Self::luma_to_shades(&luma, dithering)
+	}
+
+	/// One shade character per pixel
+	fn luma_to_shades(luma: &ImageBuffer<Luma<u8>, Vec<u8>>, dithering: Dithering) -> Vec<String> {
+		const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+		let levels = dither::quantize_luma(luma, SHADES.len() as u8, dithering);
+		levels
+			.chunks(luma.width().max(1) as usize)
+			.map(|row| row.iter().map(|&level| SHADES[level as usize]).collect())
+			.collect()
 	}
 	
-	fn img_to_gray_unicode(img: DynamicImage, dims: Vec2, scale_method: ScaleMode) -> Vec<String> {
+	fn img_to_gray_unicode(img: &DynamicImage, dims: Vec2, scale_method: ScaleMode, dithering: Dithering) -> Vec<String> {
 		if let Ok(scale_method) = scale_method.try_into() { 
 			Self::resized_i2g_unicode(
 				&img.resize(dims.x as u32, dims.y as u32 * 2, scale_method).into_luma8(), 
-				dims
+				dithering
 			)
 		} else {
-			Self::n64_i2g_unicode(&img, dims)
+			Self::n64_i2g_unicode(img, dims, dithering)
 		}
 
 		
 	}
 
+	fn draw_styled(printer: &cursive::Printer, styled: &[StyledString]) {
+		for y in 0..printer.output_size.y {
+			if let Some(line) = styled.get(y) {
+				printer.print_styled((0, y), line.into());
+			} else {
+				// printer is bigger than our image; no further lines will be valid
+				return;
+			}
+		}
+	}
+
 }
 
 impl View for ImageView {
 	fn draw(&self, printer: &cursive::Printer) {
-		match &self.rendered {
-		    ImageRenderable::Styled(styled) => {
-				for y in 0..printer.output_size.y {
-					if let Some(line) = styled.get(y) {
-						printer.print_styled((0, y), line.into());
-					} else {
-						// printer is bigger than our image; no further lines will be valid
-						return;
-					}
-				}
+		self.draw_image(printer);
+		if self.is_selected(printer) {
+			printer.with_effect(Effect::Reverse, |printer| printer.print((0, 0), "[Enter: view]"));
+		}
+	}
+	
+	fn required_size(&mut self, _: Vec2) -> Vec2 {
+		match &*self.rendered.lock().unwrap() {
+			Some(loaded) => loaded.size,
+			None => self.dims,
+		}
+	}
+
+	fn take_focus(&mut self, _: Direction) -> bool {
+		self.on_submit.is_some()
+	}
+
+	fn on_event(&mut self, event: Event) -> EventResult {
+		match (&self.on_submit, event) {
+			(Some(cb), Event::Key(Key::Enter)) => {
+				let cb = cb.clone();
+				EventResult::with_cb(move |c| cb(c))
 			}
+			_ => EventResult::Ignored,
+		}
+	}
+}
+
+impl ImageView {
+	/// Whether the image has the focus and can be opened, which `draw` shows with a hint
+	fn is_selected(&self, printer: &cursive::Printer) -> bool {
+		printer.focused && self.on_submit.is_some()
+	}
+
+	fn draw_image(&self, printer: &cursive::Printer) {
+		let loaded = self.rendered.lock().unwrap();
+		let (rendered, size) = match &*loaded {
+			Some(loaded) => (&loaded.rendered, loaded.size),
+			None => {
+				printer.print((0, 0), "loading...");
+				return;
+			}
+		};
+		match rendered {
+		    ImageRenderable::Styled(styled) => Self::draw_styled(printer, styled),
 		    ImageRenderable::Raw(strs) => {
 				for y in 0..printer.output_size.y {
 					if let Some(line) = strs.get(y) {
@@ -221,18 +514,30 @@ impl View for ImageView {
 					}
 				}
 			}
+			ImageRenderable::Graphics { fallback, image } => {
+				// a graphic would cover the focus hint, so the selected thumbnail shows it's fallback instead
+				if !self.is_selected(printer) && graphics::can_place(printer, size) {
+					// the cells are left empty for the image, which is drawn over them once the frame is on screen
+					for y in 0..size.y {
+						printer.print_hline((0, y), size.x, " ");
+					}
+					graphics::place(printer.offset, image.clone());
+				} else {
+					Self::draw_styled(printer, fallback);
+				}
+			}
 		   //  ImageRenderable::Gui(_) => {}
 		}
 	}
-	
-	fn required_size(&mut self, _: Vec2) -> Vec2 {
-		self.size
-	}
 }
 
 
-fn decode_image(buffer: &[u8]) -> DynamicImage {
-	image::load_from_memory(buffer).unwrap()
+fn fetch_image(url: &str) -> Result<DynamicImage, Box<dyn Error>> {
+	Ok(decode_image(crate::net::get_bytes(url)?.as_ref())?)
+}
+
+fn decode_image(buffer: &[u8]) -> ImageResult<DynamicImage> {
+	image::load_from_memory(buffer)
 }
 
 pub struct Divider {
@@ -268,12 +573,62 @@ mod tests {
 	use std::fs::read_to_string;
 
     use cursive::{direction::Orientation, views::{Button, LinearLayout}};
+    use image::{DynamicImage, RgbImage};
     use test::Bencher;
 
-    use crate::{SettingsAndData, data::{BoardsResponse, Post}};
+    use crate::{SettingsAndData, config::ColorDepth, data::{BoardsResponse, Post}};
 
-    use super::{Divider, ScaleMode, ImageView};
+    use super::{Divider, Dithering, RenderMode, ScaleMode, ImageView, blocks::{self, Glyphs}};
 	
+	// a smooth gradient with some detail, about the size of a thumbnail
+	fn bench_image() -> DynamicImage {
+		DynamicImage::ImageRgb8(RgbImage::from_fn(400, 400, |x, y| {
+			image::Rgb([(x * 255 / 400) as u8, (y * 255 / 400) as u8, ((x ^ y) & 0xff) as u8])
+		}))
+	}
+
+	#[test]
+	fn test_render_short_image() {
+		// a wide banner fitted into a thumbnail is less than one cell tall
+		let img = DynamicImage::ImageRgb8(RgbImage::new(250, 12));
+		for render_mode in [RenderMode::Color, RenderMode::Grayscale, RenderMode::Sixel, RenderMode::Quadrant, RenderMode::Sextant, RenderMode::Braille, RenderMode::BrailleColor] {
+			let loaded = ImageView::render(&img, Vec2::new(20, 20), render_mode, ScaleMode::Linear, Dithering::None);
+			assert!(loaded.size.y <= 1, "{:?}", render_mode);
+		}
+	}
+
+	#[bench]
+	fn bench_half_block_render(b: &mut Bencher) {
+		let img = bench_image();
+		b.iter(|| ImageView::img_to_color_unicode(&img, Vec2::new(40, 20), ScaleMode::Linear, Dithering::None));
+	}
+
+	#[bench]
+	fn bench_quadrant_render(b: &mut Bencher) {
+		let img = bench_image();
+		b.iter(|| ImageView::img_to_block_unicode(&img, Vec2::new(40, 20), ScaleMode::Linear, Glyphs::Quadrants, Dithering::None));
+	}
+
+	#[bench]
+	fn bench_sextant_render(b: &mut Bencher) {
+		let img = bench_image();
+		b.iter(|| ImageView::img_to_block_unicode(&img, Vec2::new(40, 20), ScaleMode::Linear, Glyphs::Sextants, Dithering::None));
+	}
+
+	#[bench]
+	fn bench_sextant_fit(b: &mut Bencher) {
+		// just the fitting, without the resize
+		let img = bench_image().resize_exact(80, 60, image::imageops::FilterType::Triangle).into_rgb8();
+		b.iter(|| blocks::render(&img, Glyphs::Sextants, Dithering::None, ColorDepth::TrueColor));
+	}
+	
+	#[bench]
+	fn bench_sixel_encode(b: &mut Bencher) {
+		// a screen full of photo-like colors, far more than fit in a sixel palette
+		let img = bench_image().resize_exact(960, 540, image::imageops::FilterType::Triangle).into_rgb8();
+		b.iter(|| super::graphics::sixel::encode(&img));
+	}
+
 	#[bench]
 	fn bench_thread_list_creation(_: &mut Bencher) {
 		let to_deserialize = read_to_string("assets/test/benchdata.json").unwrap();
@@ -282,11 +637,14 @@ mod tests {
 			show_nsfw: false,
 			scale_mode: ScaleMode::Linear,
 		   render_mode: super::RenderMode::Color,
+			dithering: super::Dithering::None,
 			
 			boards: BoardsResponse{
 			    boards: Vec::new(),
 			    troll_flags: None,
 			},
+			watcher: Default::default(),
+			watch_list_path: None,
 		};
 
 		let mut threads_view = LinearLayout::new(Orientation::Vertical);
@@ -343,7 +701,8 @@ mod tests {
 						format!("http://dernia/bench.jpg"), 
 						Vec2::new(20, 10), 
 						super::RenderMode::Grayscale, 
-						img_scale_method
+						img_scale_method,
+						super::Dithering::None,
 					)
 				);
 			}
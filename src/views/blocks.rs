@@ -0,0 +1,186 @@
+//! Rendering images with block glyphs that split a cell into 2×2 (quadrants) or 2×3 (sextants) pixels,
+//! each cell showing the two colors that fit it's pixels best
+
+use cursive::{theme::{ColorStyle, ColorType, Style}, utils::markup::StyledString};
+use image::{Rgb, RgbImage};
+
+use super::{Dithering, palette};
+use crate::config::ColorDepth;
+
+// quadrant glyph for every combination of set pixels, bit 0 is the top left pixel, bit 1 the top right, ...
+const QUADRANTS: [char; 16] = [' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█'];
+
+/// How a cell is split up
+#[derive(Debug, Clone, Copy)]
+pub enum Glyphs {
+	// 2×2, available in about every font
+	Quadrants,
+	// 2×3, needs a font with the sextants added in Unicode 13
+	Sextants,
+}
+
+impl Glyphs {
+	/// Pixels per cell, horizontally and vertically
+	pub fn cell_size(self) -> (usize, usize) {
+		match self {
+			Glyphs::Quadrants => (2, 2),
+			Glyphs::Sextants => (2, 3),
+		}
+	}
+
+	/// The glyph showing the pixels set in `mask` in the foreground color, bit 0 being the top left pixel
+	fn glyph(self, mask: usize) -> char {
+		match (self, mask) {
+			(Glyphs::Quadrants, _) => QUADRANTS[mask],
+			(Glyphs::Sextants, 0) => ' ',
+			(Glyphs::Sextants, 63) => '█',
+			// the sextants that are also half blocks aren't encoded twice
+			(Glyphs::Sextants, 21) => '▌',
+			(Glyphs::Sextants, 42) => '▐',
+			// U+1FB00 is the first pattern, and the patterns follow in order of their mask
+			(Glyphs::Sextants, _) => {
+				let skipped = (mask > 21) as usize + (mask > 42) as usize;
+				std::char::from_u32(0x1FB00 + (mask - 1 - skipped) as u32).unwrap()
+			}
+		}
+	}
+}
+
+fn mean(pixels: &[[u8; 3]], mask: usize, set: bool) -> Option<[f32; 3]> {
+	let mut sum = [0.0; 3];
+	let mut count = 0;
+	for (i, pixel) in pixels.iter().enumerate() {
+		if (mask >> i & 1 == 1) == set {
+			for channel in 0..3 {
+				sum[channel] += pixel[channel] as f32;
+			}
+			count += 1;
+		}
+	}
+	(count > 0).then(|| sum.map(|channel| channel / count as f32))
+}
+
+fn squared_error(pixels: &[[u8; 3]], mask: usize, foreground: [f32; 3], background: [f32; 3]) -> f32 {
+	pixels
+		.iter()
+		.enumerate()
+		.map(|(i, pixel)| {
+			let color = if mask >> i & 1 == 1 { foreground } else { background };
+			(0..3).map(|channel| (pixel[channel] as f32 - color[channel]).powi(2)).sum::<f32>()
+		})
+		.sum()
+}
+
+/// Splits the pixels of a cell into the two groups whose average colors are closest to them.
+/// Returns the mask of the foreground pixels, and the foreground and background colors.
+fn best_fit(pixels: &[[u8; 3]]) -> (usize, [f32; 3], [f32; 3]) {
+	let mut best = (0, [0.0; 3], mean(pixels, 0, false).unwrap(), f32::INFINITY);
+	// swapping the groups gives the same fit, so the last pixel is always in the background
+	for mask in 0..1 << (pixels.len() - 1) {
+		let background = mean(pixels, mask, false).unwrap();
+		let foreground = mean(pixels, mask, true).unwrap_or(background);
+		let error = squared_error(pixels, mask, foreground, background);
+		if error < best.3 {
+			best = (mask, foreground, background, error);
+		}
+	}
+	(best.0, best.1, best.2)
+}
+
+fn to_rgb(color: [f32; 3]) -> Rgb<u8> {
+	Rgb(color.map(|channel| channel.round() as u8))
+}
+
+/// Renders `img` with a cell for every `glyphs.cell_size()` pixels, in colors that the terminal can show at `depth`
+pub fn render(img: &RgbImage, glyphs: Glyphs, dithering: Dithering, depth: ColorDepth) -> Vec<StyledString> {
+	let (cell_width, cell_height) = glyphs.cell_size();
+	let (width, height) = (img.width() as usize, img.height() as usize);
+	let (cols, rows) = (width.div_ceil(cell_width), height / cell_height);
+	let mut pixels = Vec::with_capacity(cell_width * cell_height);
+	let mut masks = Vec::with_capacity(cols * rows);
+	let mut foregrounds = RgbImage::new(cols as u32, rows as u32);
+	let mut backgrounds = RgbImage::new(cols as u32, rows as u32);
+	for row in 0..rows {
+		for col in 0..cols {
+			pixels.clear();
+			for dy in 0..cell_height {
+				for dx in 0..cell_width {
+					// an odd width leaves the last column half empty, which is filled by repeating the edge
+					let x = (col * cell_width + dx).min(width - 1);
+					pixels.push(img.get_pixel(x as u32, (row * cell_height + dy) as u32).0);
+				}
+			}
+			let (mask, foreground, background) = best_fit(&pixels);
+			masks.push(mask);
+			foregrounds.put_pixel(col as u32, row as u32, to_rgb(foreground));
+			backgrounds.put_pixel(col as u32, row as u32, to_rgb(background));
+		}
+	}
+	// the foreground and background colors are reduced (and dithered) as two images of a pixel per cell,
+	// so the error of one cell's foreground is made up for by the foregrounds around it
+	let foregrounds = palette::quantize(&foregrounds, depth, dithering);
+	let backgrounds = palette::quantize(&backgrounds, depth, dithering);
+	masks
+		.chunks(cols.max(1))
+		.enumerate()
+		.map(|(row, masks)| {
+			let mut line = StyledString::new();
+			for (col, &mask) in masks.iter().enumerate() {
+				let i = row * cols + col;
+				let style = Style::from(ColorStyle::new(ColorType::Color(foregrounds[i]), ColorType::Color(backgrounds[i])));
+				line.append_styled(glyphs.glyph(mask).to_string(), style);
+			}
+			line
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use cursive::theme::{BaseColor, Color, ColorType};
+	use image::{Rgb, RgbImage};
+
+	use super::{Glyphs, render};
+	use crate::{config::ColorDepth, views::Dithering};
+
+	#[test]
+	fn test_glyphs() {
+		assert_eq!(Glyphs::Sextants.glyph(1), '\u{1FB00}');
+		assert_eq!(Glyphs::Sextants.glyph(20), '\u{1FB13}');
+		assert_eq!(Glyphs::Sextants.glyph(22), '\u{1FB14}');
+		assert_eq!(Glyphs::Sextants.glyph(62), '\u{1FB3B}');
+		assert_eq!(Glyphs::Sextants.glyph(21), '▌');
+	}
+
+	#[test]
+	fn test_render() {
+		let white = Rgb([255, 255, 255]);
+		let black = Rgb([0, 0, 0]);
+		// a diagonal, and a cell with it's top row set
+		let img = RgbImage::from_fn(4, 2, |x, y| if (x < 2 && x == y) || (x >= 2 && y == 0) { white } else { black });
+		let lines = render(&img, Glyphs::Quadrants, Dithering::None, ColorDepth::TrueColor);
+		assert_eq!(lines.len(), 1);
+		// the last pixel of a cell is always part of the background
+		assert_eq!(lines[0].source(), "▞▀");
+
+		// the right column of the top two rows
+		let img = RgbImage::from_fn(2, 3, |x, y| if x == 1 && y < 2 { white } else { black });
+		assert_eq!(render(&img, Glyphs::Sextants, Dithering::None, ColorDepth::TrueColor)[0].source(), "\u{1FB09}");
+	}
+
+	#[test]
+	fn test_render_dithered() {
+		// a flat orange between the 16 colors, which only dithering can get close to
+		let img = RgbImage::from_pixel(16, 16, Rgb([230, 120, 20]));
+		let backgrounds = |dithering| -> Vec<ColorType> {
+			render(&img, Glyphs::Quadrants, dithering, ColorDepth::Ansi16)
+				.iter()
+				.flat_map(|line| line.spans().map(|span| span.attr.color.back).collect::<Vec<_>>())
+				.collect()
+		};
+		let plain = backgrounds(Dithering::None);
+		assert!(plain.iter().all(|&color| color == ColorType::Color(Color::Dark(BaseColor::Yellow))));
+		let dithered = backgrounds(Dithering::FloydSteinberg);
+		assert!(dithered.iter().any(|&color| color != dithered[0]));
+	}
+}
@@ -0,0 +1,97 @@
+//! Rendering images as Braille patterns, which fit 2×4 dots in a cell
+
+use cursive::{theme::Style, utils::markup::StyledString};
+use image::{RgbImage, imageops};
+
+use super::{Dithering, dither, palette};
+use crate::config::ColorDepth;
+
+// bit of the dot at [x][y] within a cell
+const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+/// The Braille pattern of every cell, and the pixels whose dots are set
+fn cells(img: &RgbImage, dithering: Dithering) -> Vec<Vec<(char, Vec<[u8; 3]>)>> {
+	let lit = dither::quantize_luma(&imageops::grayscale(img), 2, dithering);
+	let (width, height) = (img.width() as usize, img.height() as usize);
+	(0..height.div_ceil(4))
+		.map(|row| {
+			(0..width.div_ceil(2))
+				.map(|col| {
+					let mut bits = 0;
+					let mut colors = Vec::new();
+					for (dx, column) in DOTS.iter().enumerate() {
+						for (dy, bit) in column.iter().enumerate() {
+							let (x, y) = (col * 2 + dx, row * 4 + dy);
+							if x < width && y < height && lit[y * width + x] == 1 {
+								bits |= bit;
+								colors.push(img.get_pixel(x as u32, y as u32).0);
+							}
+						}
+					}
+					(std::char::from_u32(0x2800 + bits).unwrap(), colors)
+				})
+				.collect()
+		})
+		.collect()
+}
+
+/// Renders an image with a dot for every light pixel
+pub fn render(img: &RgbImage, dithering: Dithering) -> Vec<String> {
+	cells(img, dithering)
+		.into_iter()
+		.map(|row| row.into_iter().map(|(pattern, _)| pattern).collect())
+		.collect()
+}
+
+/// Renders an image with a dot for every light pixel, coloring each cell with the average color of it's dots
+/// (or the closest one there is at `depth`)
+pub fn render_color(img: &RgbImage, dithering: Dithering, depth: ColorDepth) -> Vec<StyledString> {
+	cells(img, dithering)
+		.into_iter()
+		.map(|row| {
+			let mut line = StyledString::new();
+			for (pattern, colors) in row {
+				if colors.is_empty() {
+					line.append_plain(pattern.to_string());
+					continue;
+				}
+				let mut sum = [0usize; 3];
+				for color in &colors {
+					for channel in 0..3 {
+						sum[channel] += color[channel] as usize;
+					}
+				}
+				let average = sum.map(|channel| (channel / colors.len()) as u8);
+				line.append_styled(pattern.to_string(), Style::from(palette::nearest(average, depth)));
+			}
+			line
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use cursive::theme::{BaseColor, Color, ColorType};
+	use image::{Rgb, RgbImage};
+
+	use super::{render, render_color};
+	use crate::{config::ColorDepth, views::Dithering};
+
+	#[test]
+	fn test_render() {
+		let white = Rgb([255, 255, 255]);
+		let black = Rgb([0, 0, 0]);
+		// a full cell, the left column of a cell, and a cell cut off after 2 of it's 4 rows
+		let img = RgbImage::from_fn(4, 6, |x, _| if x == 3 { black } else { white });
+		assert_eq!(render(&img, Dithering::None), vec!["⣿⡇", "⠛⠃"]);
+
+		let img = RgbImage::from_fn(2, 4, |x, _| if x == 0 { Rgb([255, 255, 0]) } else { Rgb([0, 0, 128]) });
+		let lines = render_color(&img, Dithering::None, ColorDepth::TrueColor);
+		assert_eq!(lines[0].source(), "⡇");
+		let style = lines[0].spans().next().unwrap().attr;
+		assert_eq!(style.color.front, ColorType::Color(Color::Rgb(255, 255, 0)));
+		let lines = render_color(&img, Dithering::None, ColorDepth::Ansi16);
+		let style = lines[0].spans().next().unwrap().attr;
+		assert_eq!(style.color.front, ColorType::Color(Color::Light(BaseColor::Yellow)));
+	}
+}
@@ -0,0 +1,199 @@
+use std::{ops::Range, rc::Rc};
+
+use cursive::{Cursive, Printer, Rect, Vec2, View, direction::{Absolute, Direction}, event::{Event, EventResult, Key}, theme::Effect, utils::{lines::spans::{LinesIterator, Row}, markup::StyledString, span::SpannedStr}};
+
+use crate::{html, quotes::QuoteLink};
+
+type LinkCallback = Rc<dyn Fn(&mut Cursive, &QuoteLink)>;
+
+/// Displays a post comment with selectable quotelinks.
+/// Left/Right (and Up/Down across lines) move between links, Enter submits the selected one.
+pub struct CommentView {
+	content: StyledString,
+	links:   Vec<(Range<usize>, QuoteLink)>,
+	focused: usize,
+	rows:    Vec<Row>,
+	// width the rows were last wrapped at
+	rows_width: Option<usize>,
+	on_link: Option<LinkCallback>,
+}
+
+impl CommentView {
+	/// Creates a view for the raw comment HTML of a post in thread `thread` on `board`
+	pub fn new(com: &str, board: &str, thread: isize) -> CommentView {
+		let comment = html::comment_to_styled(com);
+		let links = comment.links
+			.into_iter()
+			.filter_map(|link| QuoteLink::from_href(&link.href, board, thread).map(|quote| (link.spans, quote)))
+			.collect();
+		CommentView {
+			content: comment.text,
+			links,
+			focused: 0,
+			rows: Vec::new(),
+			rows_width: None,
+			on_link: None,
+		}
+	}
+
+	/// Sets the callback run when a quotelink is submitted
+	pub fn on_link<F>(mut self, cb: F) -> Self
+	where
+		F: Fn(&mut Cursive, &QuoteLink) + 'static,
+	{
+		self.on_link = Some(Rc::new(cb));
+		self
+	}
+
+	fn compute_rows(&mut self, width: usize) {
+		if self.rows_width != Some(width) {
+			self.rows = LinesIterator::new(&self.content, width).collect();
+			self.rows_width = Some(width);
+		}
+	}
+
+	/// The row the given link starts on
+	fn link_row(&self, link: usize) -> usize {
+		let spans = &self.links[link].0;
+		self.rows
+			.iter()
+			.position(|row| row.segments.iter().any(|seg| spans.contains(&seg.span_id)))
+			.unwrap_or(0)
+	}
+
+	fn submit(&self) -> EventResult {
+		match (&self.on_link, self.links.get(self.focused)) {
+			(Some(cb), Some((_, link))) => {
+				let cb = cb.clone();
+				let link = link.clone();
+				EventResult::with_cb(move |c| cb(c, &link))
+			}
+			_ => EventResult::Ignored,
+		}
+	}
+
+	/// Moves the focus to the closest link on a different row, or ignores the event if there is none
+	/// so the parent layout can move the focus to the next view.
+	fn focus_other_row(&mut self, down: bool) -> EventResult {
+		let current_row = self.link_row(self.focused);
+		let candidate = if down {
+			(self.focused + 1..self.links.len()).find(|&i| self.link_row(i) > current_row)
+		} else {
+			(0..self.focused).rev().find(|&i| self.link_row(i) < current_row)
+		};
+		match candidate {
+			Some(i) => {
+				self.focused = i;
+				EventResult::Consumed(None)
+			}
+			None => EventResult::Ignored,
+		}
+	}
+}
+
+impl View for CommentView {
+	fn draw(&self, printer: &Printer) {
+		let source: SpannedStr<_> = (&self.content).into();
+		let focused_spans = self.links.get(self.focused).filter(|_| printer.focused).map(|(spans, _)| spans);
+		for (y, row) in self.rows.iter().enumerate() {
+			let mut x = 0;
+			for segment in row.segments.iter() {
+				let span = segment.resolve(&source);
+				let mut style = *span.attr;
+				if focused_spans.is_some_and(|spans| spans.contains(&segment.span_id)) {
+					style = style.combine(Effect::Reverse);
+				}
+				printer.with_style(style, |printer| printer.print((x, y), span.content));
+				x += span.width;
+			}
+		}
+	}
+
+	fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+		self.compute_rows(constraint.x);
+		let width = if self.rows.iter().any(|row| row.is_wrapped) {
+			constraint.x
+		} else {
+			self.rows.iter().map(|row| row.width).max().unwrap_or(0)
+		};
+		Vec2::new(width, self.rows.len())
+	}
+
+	fn layout(&mut self, size: Vec2) {
+		self.compute_rows(size.x);
+	}
+
+	fn take_focus(&mut self, source: Direction) -> bool {
+		if self.links.is_empty() {
+			return false;
+		}
+		match source {
+			// coming from below, so start at the bottom
+			Direction::Abs(Absolute::Down) => self.focused = self.links.len() - 1,
+			Direction::Abs(Absolute::Up) => self.focused = 0,
+			_ => {}
+		}
+		true
+	}
+
+	fn on_event(&mut self, event: Event) -> EventResult {
+		if self.links.is_empty() {
+			return EventResult::Ignored;
+		}
+		match event {
+			Event::Key(Key::Enter) => self.submit(),
+			Event::Key(Key::Right) if self.focused + 1 < self.links.len() => {
+				self.focused += 1;
+				EventResult::Consumed(None)
+			}
+			Event::Key(Key::Left) if self.focused > 0 => {
+				self.focused -= 1;
+				EventResult::Consumed(None)
+			}
+			Event::Key(Key::Down) => self.focus_other_row(true),
+			Event::Key(Key::Up) => self.focus_other_row(false),
+			_ => EventResult::Ignored,
+		}
+	}
+
+	fn important_area(&self, view_size: Vec2) -> Rect {
+		if self.links.is_empty() {
+			return Rect::from_size((0, 0), view_size);
+		}
+		Rect::from_size((0, self.link_row(self.focused)), (view_size.x, 1))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use cursive::{Vec2, View, direction::{Absolute, Direction}, event::{Event, EventResult, Key}};
+
+	use super::CommentView;
+	use crate::quotes::QuoteLink;
+
+	#[test]
+	fn test_link_navigation() {
+		let mut view = CommentView::new(
+			"<a href=\"#p1\" class=\"quotelink\">&gt;&gt;1</a> <a href=\"#p2\" class=\"quotelink\">&gt;&gt;2</a><br>\
+			text<br><a href=\"/g/thread/5#p6\" class=\"quotelink\">&gt;&gt;6</a>",
+			"po",
+			1,
+		);
+		assert_eq!(view.required_size(Vec2::new(40, 10)), Vec2::new(18, 3));
+		assert!(view.take_focus(Direction::Abs(Absolute::Up)));
+		assert_eq!(view.links[view.focused].1, QuoteLink::Post(1));
+
+		assert!(matches!(view.on_event(Event::Key(Key::Right)), EventResult::Consumed(_)));
+		assert_eq!(view.links[view.focused].1, QuoteLink::Post(2));
+		// down skips to the link on the next line with one, and then lets the focus leave
+		assert!(matches!(view.on_event(Event::Key(Key::Down)), EventResult::Consumed(_)));
+		assert_eq!(view.links[view.focused].1, QuoteLink::CrossThread { board: "g".into(), thread: 5, post: 6 });
+		assert_eq!(view.link_row(view.focused), 2);
+		assert!(matches!(view.on_event(Event::Key(Key::Down)), EventResult::Ignored));
+		assert!(matches!(view.on_event(Event::Key(Key::Up)), EventResult::Consumed(_)));
+		assert_eq!(view.links[view.focused].1, QuoteLink::Post(2));
+
+		let mut plain = CommentView::new("no links here", "po", 1);
+		assert!(!plain.take_focus(Direction::Abs(Absolute::Up)));
+	}
+}
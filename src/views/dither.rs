@@ -0,0 +1,102 @@
+//! Reducing images to the few levels that characters can show
+
+use image::GrayImage;
+
+use super::Dithering;
+
+// how much of the rounding error goes where: (dx, dy, weight), and what the weights are divided by
+const FLOYD_STEINBERG: (&[(isize, usize, f32)], f32) = (&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0);
+// only spreads 3/4 of the error, which keeps more contrast in light and dark areas
+const ATKINSON: (&[(isize, usize, f32)], f32) = (&[(1, 0, 1.0), (2, 0, 1.0), (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0), (0, 2, 1.0)], 8.0);
+
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Quantizes `values` (`width` pixels per row) with `quantize`, which returns the level it picked and the value
+/// that level stands for. `step` is the distance between two levels, which ordered dithering needs to know.
+pub fn dither<const N: usize, T>(
+	mut values: Vec<[f32; N]>,
+	width: usize,
+	dithering: Dithering,
+	step: f32,
+	quantize: impl Fn([f32; N]) -> (T, [f32; N]),
+) -> Vec<T> {
+	let kernel = match dithering {
+		Dithering::None => return values.into_iter().map(|value| quantize(value).0).collect(),
+		Dithering::Bayer => {
+			return values
+				.into_iter()
+				.enumerate()
+				.map(|(i, value)| {
+					// shifts every pixel by up to half a level, in a pattern that averages out over 4×4 pixels
+					let threshold = (BAYER_4X4[i / width % 4][i % width % 4] as f32 + 0.5) / 16.0 - 0.5;
+					quantize(value.map(|channel| channel + threshold * step)).0
+				})
+				.collect();
+		}
+		Dithering::FloydSteinberg => FLOYD_STEINBERG,
+		Dithering::Atkinson => ATKINSON,
+	};
+	let height = values.len() / width.max(1);
+	let mut quantized = Vec::with_capacity(values.len());
+	for i in 0..values.len() {
+		let (x, y) = (i % width, i / width);
+		let (level, value) = quantize(values[i]);
+		quantized.push(level);
+		// whatever rounding took away is made up for by the neighbours that haven't been quantized yet
+		let mut error = [0.0; N];
+		for channel in 0..N {
+			error[channel] = values[i][channel] - value[channel];
+		}
+		for &(dx, dy, weight) in kernel.0 {
+			let nx = x as isize + dx;
+			if nx >= 0 && (nx as usize) < width && y + dy < height {
+				let neighbour = &mut values[(y + dy) * width + nx as usize];
+				for channel in 0..N {
+					neighbour[channel] += error[channel] * weight / kernel.1;
+				}
+			}
+		}
+	}
+	quantized
+}
+
+/// Reduces every pixel of `img` to one of `levels` evenly spaced brightness levels.
+/// Returns the level (`0..levels`) of every pixel in row-major order.
+pub fn quantize_luma(img: &GrayImage, levels: u8, dithering: Dithering) -> Vec<u8> {
+	if let Dithering::None = dithering {
+		// equally wide bins, so every level gets the same share of the brightness range
+		return img.pixels().map(|pixel| (pixel.0[0] as usize * levels as usize / 256) as u8).collect();
+	}
+	let max = (levels - 1) as f32;
+	let step = 255.0 / max;
+	let values = img.pixels().map(|pixel| [pixel.0[0] as f32]).collect();
+	dither(values, img.width() as usize, dithering, step, |[value]| {
+		let level = (value / step).round().clamp(0.0, max);
+		(level as u8, [level * step])
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use image::{GrayImage, Luma};
+
+	use super::quantize_luma;
+	use crate::views::Dithering;
+
+	#[test]
+	fn test_quantize_luma() {
+		let gray = GrayImage::from_pixel(8, 8, Luma([128]));
+		assert!(quantize_luma(&gray, 2, Dithering::None).iter().all(|&level| level == 1));
+		// dithering turns 50% gray into about as many light pixels as dark ones
+		for dithering in [Dithering::Bayer, Dithering::FloydSteinberg, Dithering::Atkinson] {
+			let lit = quantize_luma(&gray, 2, dithering).iter().filter(|&&level| level == 1).count();
+			assert!((28..=36).contains(&lit), "{} of 64 pixels lit with {:?}", lit, dithering);
+		}
+		// ordered dithering repeats every 4 pixels
+		let bayer = quantize_luma(&GrayImage::from_pixel(8, 1, Luma([64])), 2, Dithering::Bayer);
+		assert_eq!(bayer[..4], bayer[4..]);
+
+		let gradient = GrayImage::from_fn(5, 1, |x, _| Luma([[0, 51, 52, 204, 255][x as usize]]));
+		assert_eq!(quantize_luma(&gradient, 5, Dithering::None), vec![0, 0, 1, 3, 4]);
+	}
+}
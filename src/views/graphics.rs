@@ -0,0 +1,275 @@
+//! Drawing real images with terminal graphics protocols.
+//!
+//! Cursive only knows about character cells, so `ImageView`s that use a graphics protocol reserve their cells
+//! while the frame is drawn and queue their image with `place`. `flush` writes the queued images over the
+//! reserved cells once cursive has put the frame on screen.
+
+use std::{collections::HashSet, io::{self, Write}, sync::{Arc, Mutex, OnceLock, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
+
+use cursive::{Printer, Vec2, View, view::ViewWrapper, wrap_impl};
+use image::{ColorType, ImageResult, RgbaImage, codecs::png::PngEncoder};
+
+pub mod iterm;
+pub mod kitty;
+pub mod sixel;
+
+/// What the terminal told us it can do
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Capabilities {
+	pub sixel: bool,
+	pub kitty: bool,
+	pub iterm: bool,
+}
+
+/// An image in the format of one of the graphics protocols
+#[derive(Debug, Clone)]
+pub enum Graphic {
+	// an escape sequence that draws the image at the cursor (sixel, iTerm2 inline images)
+	Inline(Arc<Vec<u8>>),
+	Kitty(Arc<kitty::KittyImage>),
+}
+
+static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+
+// images waiting to be written, with their top left cell on screen
+static PENDING: Mutex<Vec<(Vec2, Graphic)>> = Mutex::new(Vec::new());
+
+// kitty images stay on screen until they're deleted, so we have to remember which ones were placed in the last frame
+static KITTY_PLACED: Mutex<Option<HashSet<u32>>> = Mutex::new(None);
+
+// escape sequences freeing images that are no longer used
+static DISCARDED: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+// set while a popup is open, since images would be drawn over it
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+/// Parses the parameters of a primary device attributes response (`ESC [ ? 62 ; 4 ; 22 c`)
+fn parse_device_attributes(response: &[u8]) -> Option<Vec<u32>> {
+	let start = response.windows(3).position(|w| w == b"\x1b[?")? + 3;
+	let len = response[start..].iter().position(|&b| b == b'c')?;
+	std::str::from_utf8(&response[start..start + len])
+		.ok()?
+		.split(';')
+		.map(|param| param.parse().ok())
+		.collect()
+}
+
+/// Asks the terminal whether it supports the kitty graphics protocol and for it's primary device attributes.
+/// Returns everything the terminal answered. Has to be done before cursive takes over the terminal.
+fn query_terminal() -> Vec<u8> {
+	// SAFETY: only queries and changes the terminal settings of stdin, which are restored before returning
+	let original = unsafe {
+		if libc::isatty(libc::STDIN_FILENO) == 0 || libc::isatty(libc::STDOUT_FILENO) == 0 {
+			return Vec::new();
+		}
+		let mut original: libc::termios = std::mem::zeroed();
+		if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+			return Vec::new();
+		}
+		// the response shouldn't be echoed, and has to be readable before the user presses enter
+		let mut raw = original;
+		raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+		libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+		original
+	};
+
+	let mut response = Vec::new();
+	let mut stdout = io::stdout();
+	// terminals that don't know the kitty query ignore it, and everyone answers the device attributes query,
+	// so once that answer is in any answer to the kitty query would have been too
+	let query = b"\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[c";
+	if stdout.write_all(query).and_then(|_| stdout.flush()).is_ok() {
+		// terminals answer right away, the timeout is only for the ones that don't answer at all
+		let deadline = Instant::now() + Duration::from_millis(200);
+		while parse_device_attributes(&response).is_none() {
+			let timeout = deadline.saturating_duration_since(Instant::now());
+			let mut fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+			// SAFETY: `fd` is a single valid pollfd
+			if timeout.is_zero() || unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) } <= 0 {
+				break;
+			}
+			// read directly instead of through `io::stdin`, whose buffer could swallow input meant for cursive
+			let mut buf = [0u8; 64];
+			// SAFETY: reads at most `buf.len()` bytes into `buf`
+			let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+			if n <= 0 {
+				break;
+			}
+			response.extend_from_slice(&buf[..n as usize]);
+		}
+	}
+
+	// SAFETY: restores the settings we got from `tcgetattr`
+	unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original) };
+	response
+}
+
+/// Finds the capabilities in the terminal's answers to `query_terminal`
+fn parse_capabilities(response: &[u8]) -> Capabilities {
+	let attributes = parse_device_attributes(response);
+	// the first parameter is the terminal's class, the rest are the extensions it supports
+	let sixel = attributes.is_some_and(|attributes| attributes.iter().skip(1).any(|&attribute| attribute == 4));
+	let kitty_ok = b"\x1b_Gi=31;OK";
+	let kitty = response.windows(kitty_ok.len()).any(|w| w == kitty_ok);
+	Capabilities { sixel, kitty, iterm: false }
+}
+
+/// Finds out which graphics protocols the terminal supports
+pub fn detect() -> Capabilities {
+	*CAPABILITIES.get_or_init(|| {
+		let response = query_terminal();
+		log::info!("Terminal capability query response: {:?}", String::from_utf8_lossy(&response));
+		let term_program = std::env::var("TERM_PROGRAM").ok();
+		let lc_terminal = std::env::var("LC_TERMINAL").ok();
+		Capabilities {
+			iterm: iterm::supported_by(term_program.as_deref(), lc_terminal.as_deref()),
+			..parse_capabilities(&response)
+		}
+	})
+}
+
+/// Whether sixel images can be shown, `false` if `detect` was never called
+pub fn supports_sixel() -> bool {
+	CAPABILITIES.get().is_some_and(|capabilities| capabilities.sixel)
+}
+
+/// Whether the kitty graphics protocol can be used, `false` if `detect` was never called
+pub fn supports_kitty() -> bool {
+	CAPABILITIES.get().is_some_and(|capabilities| capabilities.kitty)
+}
+
+/// Whether iTerm2 inline images can be shown, `false` if `detect` was never called
+pub fn supports_iterm() -> bool {
+	CAPABILITIES.get().is_some_and(|capabilities| capabilities.iterm)
+}
+
+/// Encodes an image as a PNG, for the protocols that take image files
+pub fn encode_png(img: &RgbaImage) -> ImageResult<Vec<u8>> {
+	let mut png = Vec::new();
+	PngEncoder::new(&mut png).encode(img, img.width(), img.height(), ColorType::Rgba8)?;
+	Ok(png)
+}
+
+fn window_size() -> Option<libc::winsize> {
+	// SAFETY: TIOCGWINSZ only writes to the winsize we pass in
+	unsafe {
+		let mut size: libc::winsize = std::mem::zeroed();
+		(libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0).then_some(size)
+	}
+}
+
+/// Size of a character cell in pixels, if the terminal reports it
+pub fn cell_size() -> Option<Vec2> {
+	let size = window_size()?;
+	if size.ws_xpixel == 0 || size.ws_ypixel == 0 || size.ws_col == 0 || size.ws_row == 0 {
+		return None;
+	}
+	Some(Vec2::new((size.ws_xpixel / size.ws_col) as usize, (size.ws_ypixel / size.ws_row) as usize))
+}
+
+/// Whether an image of `size` cells can be drawn by `printer`.
+/// Images can't be cropped, so they're only placed if they're fully visible.
+pub fn can_place(printer: &Printer, size: Vec2) -> bool {
+	let fits = printer.content_offset == Vec2::zero() && printer.output_size.x >= size.x && printer.output_size.y >= size.y;
+	// terminals scroll if an image touches the last line
+	let above_last_line = window_size().is_some_and(|window| printer.offset.y + size.y < window.ws_row as usize);
+	!SUSPENDED.load(Ordering::Relaxed) && fits && above_last_line
+}
+
+/// Queues an image to be drawn with it's top left corner at `offset`
+pub fn place(offset: Vec2, graphic: Graphic) {
+	PENDING.lock().unwrap().push((offset, graphic));
+}
+
+/// Queues an escape sequence that frees an image, to be sent with the next frame
+fn discard(sequence: Vec<u8>) {
+	DISCARDED.lock().unwrap().push(sequence);
+}
+
+/// Stops (or resumes) placing images. Returns whether that changed anything, in which case the screen should be redrawn.
+pub fn set_suspended(suspended: bool) -> bool {
+	if suspended {
+		PENDING.lock().unwrap().clear();
+	}
+	SUSPENDED.swap(suspended, Ordering::Relaxed) != suspended
+}
+
+/// Writes all images queued while drawing the last frame to the terminal, and hides the kitty images that weren't.
+/// Has to be called after every frame cursive draws, once it's on screen.
+pub fn flush() -> io::Result<()> {
+	let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+	let mut out = Vec::new();
+	let mut kitty_placed = HashSet::new();
+	for (offset, graphic) in pending {
+		// save the cursor, move to the image's cell, draw, and put the cursor back where cursive left it
+		write!(out, "\x1b7\x1b[{};{}H", offset.y + 1, offset.x + 1)?;
+		match graphic {
+			Graphic::Inline(sequence) => out.extend_from_slice(&sequence),
+			Graphic::Kitty(image) => {
+				out.extend(image.place());
+				kitty_placed.insert(image.id);
+			}
+		}
+		out.extend_from_slice(b"\x1b8");
+	}
+	// views that scrolled out of sight or were covered up
+	let mut last_placed = KITTY_PLACED.lock().unwrap();
+	for &id in last_placed.iter().flatten().filter(|id| !kitty_placed.contains(id)) {
+		out.extend(kitty::delete_placement(id));
+	}
+	*last_placed = Some(kitty_placed);
+	for sequence in std::mem::take(&mut *DISCARDED.lock().unwrap()) {
+		out.extend(sequence);
+	}
+
+	if out.is_empty() {
+		return Ok(());
+	}
+	let mut stdout = io::stdout().lock();
+	stdout.write_all(&out)?;
+	stdout.flush()
+}
+
+/// Wraps a full screen layer. Images of the layers below it are covered by it, so they're dropped when it's drawn.
+pub struct Layer<V> {
+	view: V,
+}
+
+impl<V> Layer<V> {
+	pub fn new(view: V) -> Layer<V> {
+		Layer { view }
+	}
+}
+
+impl<V: View> ViewWrapper for Layer<V> {
+	wrap_impl!(self.view: V);
+
+	fn wrap_draw(&self, printer: &Printer) {
+		PENDING.lock().unwrap().clear();
+		self.view.draw(printer);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_capabilities, parse_device_attributes};
+
+	#[test]
+	fn test_parse_device_attributes() {
+		assert_eq!(parse_device_attributes(b"\x1b[?62;4;22c"), Some(vec![62, 4, 22]));
+		// keys pressed before the response arrived are skipped
+		assert_eq!(parse_device_attributes(b"j\x1b[?65;1;9c"), Some(vec![65, 1, 9]));
+		assert_eq!(parse_device_attributes(b"\x1b[?62;4"), None);
+		assert_eq!(parse_device_attributes(b""), None);
+	}
+
+	#[test]
+	fn test_parse_capabilities() {
+		let kitty = parse_capabilities(b"\x1b_Gi=31;OK\x1b\\\x1b[?62;22;52c");
+		assert!(kitty.kitty && !kitty.sixel);
+		let foot = parse_capabilities(b"\x1b[?62;4;22;28c");
+		assert!(foot.sixel && !foot.kitty);
+		let neither = parse_capabilities(b"\x1b[?1;2c");
+		assert!(!neither.sixel && !neither.kitty);
+	}
+}
@@ -0,0 +1,46 @@
+use std::io::Write;
+
+/// Whether the terminal we're running in speaks iTerm2's inline image protocol.
+/// There's no query for it, so this goes by the variables the terminals set.
+pub fn supported_by(term_program: Option<&str>, lc_terminal: Option<&str>) -> bool {
+	matches!(term_program, Some("iTerm.app") | Some("WezTerm")) || lc_terminal == Some("iTerm2")
+}
+
+/// Encodes an image file (any format the terminal can decode) as an inline image,
+/// drawn at the cursor and scaled to fit `cols` × `rows` cells.
+pub fn encode(file: &[u8], cols: usize, rows: usize) -> Vec<u8> {
+	let mut out = Vec::new();
+	write!(
+		out,
+		"\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+		file.len(),
+		cols,
+		rows,
+		base64::encode(file)
+	)
+	.unwrap();
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{encode, supported_by};
+
+	#[test]
+	fn test_encode() {
+		assert_eq!(
+			String::from_utf8(encode(b"png", 20, 10)).unwrap(),
+			"\x1b]1337;File=inline=1;size=3;width=20;height=10;preserveAspectRatio=1:cG5n\x07"
+		);
+	}
+
+	#[test]
+	fn test_supported_by() {
+		assert!(supported_by(Some("iTerm.app"), None));
+		assert!(supported_by(Some("WezTerm"), None));
+		// iTerm2 sets LC_TERMINAL, which is passed on through ssh
+		assert!(supported_by(None, Some("iTerm2")));
+		assert!(!supported_by(Some("Apple_Terminal"), None));
+		assert!(!supported_by(None, None));
+	}
+}
@@ -0,0 +1,102 @@
+use std::{io::Write, sync::atomic::{AtomicBool, AtomicU32, Ordering}};
+
+// the protocol limits the base64 payload of a single escape sequence to 4096 bytes
+const CHUNK_SIZE: usize = 4096;
+
+// 0 means "no id" to the terminal
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Sends `png` to be stored under `id`, split into as many escape sequences as it takes.
+/// Nothing is shown until the image is placed.
+pub fn transmit(id: u32, png: &[u8]) -> Vec<u8> {
+	let payload = base64::encode(png);
+	let chunks: Vec<&[u8]> = payload.as_bytes().chunks(CHUNK_SIZE).collect();
+	let mut out = Vec::new();
+	for (i, chunk) in chunks.iter().enumerate() {
+		let more = (i + 1 < chunks.len()) as u8;
+		// only the first chunk carries the keys, q=2 stops the terminal from answering on stdin
+		if i == 0 {
+			write!(out, "\x1b_Ga=t,f=100,i={},q=2,m={};", id, more).unwrap();
+		} else {
+			write!(out, "\x1b_Gm={};", more).unwrap();
+		}
+		out.extend_from_slice(chunk);
+		out.extend_from_slice(b"\x1b\\");
+	}
+	out
+}
+
+/// Shows the image stored under `id` at the cursor.
+/// There's a single placement per image, so placing it again moves it instead of showing it twice.
+pub fn place(id: u32) -> Vec<u8> {
+	// C=1 leaves the cursor where it is
+	format!("\x1b_Ga=p,i={},p=1,C=1,q=2\x1b\\", id).into_bytes()
+}
+
+/// Hides the image stored under `id`, keeping it stored so it can be placed again
+pub fn delete_placement(id: u32) -> Vec<u8> {
+	format!("\x1b_Ga=d,d=i,i={},q=2\x1b\\", id).into_bytes()
+}
+
+/// Hides the image stored under `id` and frees it
+pub fn delete_image(id: u32) -> Vec<u8> {
+	format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", id).into_bytes()
+}
+
+/// An image that is sent to the terminal the first time it's placed, and freed when it's dropped
+#[derive(Debug)]
+pub struct KittyImage {
+	pub id: u32,
+	png: Vec<u8>,
+	transmitted: AtomicBool,
+}
+
+impl KittyImage {
+	pub fn new(png: Vec<u8>) -> KittyImage {
+		KittyImage { id: NEXT_ID.fetch_add(1, Ordering::Relaxed), png, transmitted: AtomicBool::new(false) }
+	}
+
+	/// The escape sequences that show the image at the cursor, transmitting it first if that hasn't happened yet
+	pub fn place(&self) -> Vec<u8> {
+		let mut out = Vec::new();
+		if !self.transmitted.swap(true, Ordering::Relaxed) {
+			out = transmit(self.id, &self.png);
+		}
+		out.extend(place(self.id));
+		out
+	}
+}
+
+impl Drop for KittyImage {
+	fn drop(&mut self) {
+		if *self.transmitted.get_mut() {
+			super::discard(delete_image(self.id));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{CHUNK_SIZE, delete_image, delete_placement, place, transmit};
+
+	#[test]
+	fn test_escape_sequences() {
+		assert_eq!(String::from_utf8(transmit(7, b"png")).unwrap(), "\x1b_Ga=t,f=100,i=7,q=2,m=0;cG5n\x1b\\");
+		assert_eq!(String::from_utf8(place(7)).unwrap(), "\x1b_Ga=p,i=7,p=1,C=1,q=2\x1b\\");
+		assert_eq!(String::from_utf8(delete_placement(7)).unwrap(), "\x1b_Ga=d,d=i,i=7,q=2\x1b\\");
+		assert_eq!(String::from_utf8(delete_image(7)).unwrap(), "\x1b_Ga=d,d=I,i=7,q=2\x1b\\");
+	}
+
+	#[test]
+	fn test_transmit_chunks() {
+		// 3 bytes become 4 base64 characters, so this is just over two chunks
+		let data = vec![0; CHUNK_SIZE / 4 * 3 * 2 + 3];
+		let out = String::from_utf8(transmit(1, &data)).unwrap();
+		let chunks: Vec<&str> = out.split_terminator("\x1b\\").collect();
+		assert_eq!(chunks.len(), 3);
+		assert!(chunks[0].starts_with("\x1b_Ga=t,f=100,i=1,q=2,m=1;"));
+		assert_eq!(chunks[0].len(), "\x1b_Ga=t,f=100,i=1,q=2,m=1;".len() + CHUNK_SIZE);
+		assert_eq!(chunks[1], format!("\x1b_Gm=1;{}", "A".repeat(CHUNK_SIZE)));
+		assert_eq!(chunks[2], "\x1b_Gm=0;AAAA");
+	}
+}
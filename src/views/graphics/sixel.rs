@@ -0,0 +1,241 @@
+use std::{collections::{BTreeMap, HashMap}, io::Write};
+
+use image::RgbImage;
+
+// the most colors most terminals will register for a single image
+const MAX_COLORS: usize = 256;
+
+type Rgb = [u8; 3];
+
+// median cut works on colors reduced to this many bits per channel, which keeps the histogram small
+const HISTOGRAM_BITS: u32 = 5;
+// the most pixels the histogram is built from, larger images are sampled evenly
+const MAX_SAMPLES: usize = 1 << 16;
+
+/// A histogram bucket: it's color with the low bits dropped, how many pixels fell into it, and the sum of their colors
+type Bucket = (Rgb, u32, [u64; 3]);
+
+/// A set of buckets that gets a single palette entry
+struct ColorBox {
+	buckets: Vec<Bucket>,
+	// the channel the buckets are spread the most over, and by how much
+	widest: (usize, u8),
+}
+
+impl ColorBox {
+	fn new(buckets: Vec<Bucket>) -> ColorBox {
+		let widest = (0..3)
+			.map(|channel| {
+				let min = buckets.iter().map(|(color, _, _)| color[channel]).min().unwrap_or(0);
+				let max = buckets.iter().map(|(color, _, _)| color[channel]).max().unwrap_or(0);
+				(channel, max - min)
+			})
+			.max_by_key(|&(_, range)| range)
+			.unwrap();
+		ColorBox { buckets, widest }
+	}
+
+	/// Splits the box at the median of it's widest channel, weighted by pixel count
+	fn split(mut self) -> (ColorBox, ColorBox) {
+		let (channel, _) = self.widest;
+		self.buckets.sort_by_key(|(color, _, _)| color[channel]);
+		let total: u32 = self.buckets.iter().map(|(_, count, _)| count).sum();
+		let mut seen = 0;
+		let mut median = self.buckets.len() - 1;
+		for (i, (_, count, _)) in self.buckets.iter().enumerate() {
+			seen += count;
+			if seen * 2 >= total {
+				median = i + 1;
+				break;
+			}
+		}
+		let upper = self.buckets.split_off(median.clamp(1, self.buckets.len() - 1));
+		(ColorBox::new(self.buckets), ColorBox::new(upper))
+	}
+
+	/// The average of all pixels in the box
+	fn average(&self) -> Rgb {
+		let total: u64 = self.buckets.iter().map(|&(_, count, _)| count as u64).sum();
+		let mut average = [0; 3];
+		for (channel, value) in average.iter_mut().enumerate() {
+			let sum: u64 = self.buckets.iter().map(|(_, _, sum)| sum[channel]).sum();
+			*value = ((sum + total / 2) / total) as u8;
+		}
+		average
+	}
+}
+
+fn bucket_of(color: Rgb) -> Rgb {
+	color.map(|channel| channel >> (8 - HISTOGRAM_BITS))
+}
+
+/// Finds the closest palette entry for every pixel, remembering the answer for every histogram bucket
+/// since looking through the whole palette for every pixel of a full screen image is too slow
+fn nearest_indices(img: &RgbImage, palette: &[Rgb]) -> Vec<u8> {
+	let mut cache = vec![None; 1 << (HISTOGRAM_BITS * 3)];
+	img.pixels()
+		.map(|pixel| {
+			let [r, g, b] = bucket_of(pixel.0);
+			let key = ((r as usize) << (HISTOGRAM_BITS * 2)) | ((g as usize) << HISTOGRAM_BITS) | b as usize;
+			*cache[key].get_or_insert_with(|| {
+				let distance = |color: &Rgb| (0..3).map(|channel| (color[channel] as i32 - pixel[channel] as i32).pow(2)).sum::<i32>();
+				(0..palette.len()).min_by_key(|&i| distance(&palette[i])).unwrap_or(0) as u8
+			})
+		})
+		.collect()
+}
+
+/// Reduces an image to at most `MAX_COLORS` colors.
+/// Returns the palette, and the palette index of every pixel in row-major order.
+fn quantize(img: &RgbImage) -> (Vec<Rgb>, Vec<u8>) {
+	// images with few enough colors are used as they are, with the colors in the order they first appear
+	let mut exact: HashMap<Rgb, u8> = HashMap::new();
+	let mut palette = Vec::new();
+	let mut indices = Vec::with_capacity(img.len() / 3);
+	for pixel in img.pixels() {
+		if palette.len() > MAX_COLORS {
+			break;
+		}
+		let index = *exact.entry(pixel.0).or_insert_with(|| {
+			palette.push(pixel.0);
+			(palette.len() - 1) as u8
+		});
+		indices.push(index);
+	}
+	if palette.len() <= MAX_COLORS {
+		return (palette, indices);
+	}
+
+	// everything else goes through median cut, over a histogram of a bounded sample of the pixels
+	let mut histogram: BTreeMap<Rgb, (u32, [u64; 3])> = BTreeMap::new();
+	let step = (img.len() / 3).div_ceil(MAX_SAMPLES).max(1);
+	for pixel in img.pixels().step_by(step) {
+		let (count, sum) = histogram.entry(bucket_of(pixel.0)).or_default();
+		*count += 1;
+		for channel in 0..3 {
+			sum[channel] += pixel[channel] as u64;
+		}
+	}
+	let mut boxes = vec![ColorBox::new(histogram.into_iter().map(|(bucket, (count, sum))| (bucket, count, sum)).collect())];
+	while boxes.len() < MAX_COLORS {
+		let widest = boxes
+			.iter()
+			.enumerate()
+			.filter(|(_, color_box)| color_box.buckets.len() > 1)
+			.max_by_key(|(_, color_box)| color_box.widest.1);
+		let (lower, upper) = match widest {
+			Some((i, _)) => boxes.swap_remove(i).split(),
+			None => break,
+		};
+		boxes.push(lower);
+		boxes.push(upper);
+	}
+	let palette: Vec<Rgb> = boxes.iter().map(ColorBox::average).collect();
+	let indices = nearest_indices(img, &palette);
+	(palette, indices)
+}
+
+/// Sixel colors are given in percent
+fn percent(value: u8) -> u32 {
+	(value as u32 * 100 + 127) / 255
+}
+
+/// Writes a row of sixel characters, collapsing runs into repeat introducers where that's shorter
+fn write_run_length(out: &mut Vec<u8>, sixels: &[u8]) {
+	let mut i = 0;
+	while i < sixels.len() {
+		let run = sixels[i..].iter().take_while(|&&sixel| sixel == sixels[i]).count();
+		if run > 3 {
+			write!(out, "!{}", run).unwrap();
+			out.push(sixels[i]);
+		} else {
+			out.extend(std::iter::repeat_n(sixels[i], run));
+		}
+		i += run;
+	}
+}
+
+/// Encodes an image as a sixel sequence, drawn at the cursor position when written to the terminal.
+/// The output only depends on the pixels of `img`.
+pub fn encode(img: &RgbImage) -> Vec<u8> {
+	let (width, height) = img.dimensions();
+	let (palette, indices) = quantize(img);
+	let mut out = Vec::new();
+	// 0;1 makes pixels we don't set keep the background, the raster attributes give the size in pixels
+	write!(out, "\x1bP0;1;0q\"1;1;{};{}", width, height).unwrap();
+	for (i, [r, g, b]) in palette.iter().enumerate() {
+		write!(out, "#{};2;{};{};{}", i, percent(*r), percent(*g), percent(*b)).unwrap();
+	}
+	let (width, height) = (width as usize, height as usize);
+	for band in (0..height).step_by(6) {
+		if band > 0 {
+			out.push(b'-');
+		}
+		// the bits of every color used in the band, filled in a single pass over it's pixels
+		let mut bands: Vec<Option<Vec<u8>>> = vec![None; palette.len()];
+		for y in band..(band + 6).min(height) {
+			for (x, &index) in indices[y * width..(y + 1) * width].iter().enumerate() {
+				bands[index as usize].get_or_insert_with(|| vec![0; width])[x] |= 1 << (y - band);
+			}
+		}
+		let mut first = true;
+		for (color, bits) in bands.into_iter().enumerate() {
+			let mut bits = match bits {
+				Some(bits) => bits,
+				None => continue,
+			};
+			if !first {
+				// back to the start of the band for the next color
+				out.push(b'$');
+			}
+			first = false;
+			write!(out, "#{}", color).unwrap();
+			// empty sixels at the end of a row don't draw anything
+			while bits.last() == Some(&0) {
+				bits.pop();
+			}
+			let sixels: Vec<u8> = bits.into_iter().map(|bits| 63 + bits).collect();
+			write_run_length(&mut out, &sixels);
+		}
+	}
+	out.extend_from_slice(b"\x1b\\");
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use image::{Rgb, RgbImage};
+
+	use super::encode;
+
+	#[test]
+	fn test_encode_golden() {
+		let red = Rgb([255, 0, 0]);
+		let blue = Rgb([0, 0, 255]);
+		let img = RgbImage::from_fn(2, 2, |x, y| if x == 1 && y == 0 { blue } else { red });
+		assert_eq!(
+			String::from_utf8(encode(&img)).unwrap(),
+			"\x1bP0;1;0q\"1;1;2;2#0;2;100;0;0#1;2;0;0;100#0BA$#1?@\x1b\\"
+		);
+
+		// runs are collapsed, and the seventh row starts a new band
+		let img = RgbImage::from_fn(10, 7, |_, y| if y == 6 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) });
+		assert_eq!(
+			String::from_utf8(encode(&img)).unwrap(),
+			"\x1bP0;1;0q\"1;1;10;7#0;2;100;100;100#1;2;0;0;0#0!10~-#1!10@\x1b\\"
+		);
+	}
+
+	#[test]
+	fn test_encode_quantized() {
+		// 1024 colors have to be reduced to fit in the palette
+		let img = RgbImage::from_fn(32, 32, |x, y| Rgb([x as u8 * 8, y as u8 * 8, (x + y) as u8]));
+		let encoded = String::from_utf8(encode(&img)).unwrap();
+		assert_eq!(encoded, String::from_utf8(encode(&img)).unwrap());
+		// palette entries are the only parts with 5 parameters
+		let colors = encoded.split('#').filter(|part| part.split(';').count() == 5).count();
+		assert!(colors > 1 && colors <= 256, "{} colors", colors);
+		assert!(encoded.starts_with("\x1bP0;1;0q\"1;1;32;32#0;2;"));
+		assert!(encoded.ends_with("\x1b\\"));
+	}
+}
@@ -0,0 +1,241 @@
+//! Fitting colors to what the terminal can show
+
+use std::{convert::TryInto, env, fs, path::PathBuf, sync::OnceLock};
+
+use cursive::theme::{BaseColor, Color};
+use image::RgbImage;
+
+use super::{Dithering, dither};
+use crate::config::ColorDepth;
+
+static COLOR_DEPTH: OnceLock<ColorDepth> = OnceLock::new();
+
+// the levels of each channel in xterm's 6×6×6 color cube
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+// what xterm shows the 16 ANSI colors as, terminals with other themes are close enough
+const ANSI_16: [(Color, [u8; 3]); 16] = [
+	(Color::Dark(BaseColor::Black),    [0, 0, 0]),
+	(Color::Dark(BaseColor::Red),      [205, 0, 0]),
+	(Color::Dark(BaseColor::Green),    [0, 205, 0]),
+	(Color::Dark(BaseColor::Yellow),   [205, 205, 0]),
+	(Color::Dark(BaseColor::Blue),     [0, 0, 238]),
+	(Color::Dark(BaseColor::Magenta),  [205, 0, 205]),
+	(Color::Dark(BaseColor::Cyan),     [0, 205, 205]),
+	(Color::Dark(BaseColor::White),    [229, 229, 229]),
+	(Color::Light(BaseColor::Black),   [127, 127, 127]),
+	(Color::Light(BaseColor::Red),     [255, 0, 0]),
+	(Color::Light(BaseColor::Green),   [0, 255, 0]),
+	(Color::Light(BaseColor::Yellow),  [255, 255, 0]),
+	(Color::Light(BaseColor::Blue),    [92, 92, 255]),
+	(Color::Light(BaseColor::Magenta), [255, 0, 255]),
+	(Color::Light(BaseColor::Cyan),    [0, 255, 255]),
+	(Color::Light(BaseColor::White),   [255, 255, 255]),
+];
+
+/// Sets the color depth images are drawn with. Only has an effect before the first image is drawn.
+pub fn set_color_depth(depth: ColorDepth) {
+	if COLOR_DEPTH.set(depth).is_err() {
+		log::warn!("The color depth was set after it was already in use");
+	}
+}
+
+pub fn color_depth() -> ColorDepth {
+	*COLOR_DEPTH.get_or_init(detect)
+}
+
+/// Works out how many colors the terminal supports from `$COLORTERM`, `$TERM` and it's terminfo entry
+pub fn detect() -> ColorDepth {
+	let colorterm = env::var("COLORTERM").ok();
+	let term = env::var("TERM").ok();
+	let terminfo = term.as_deref().and_then(terminfo_colors);
+	depth_from(colorterm.as_deref(), term.as_deref(), terminfo)
+}
+
+fn depth_from(colorterm: Option<&str>, term: Option<&str>, terminfo_colors: Option<i32>) -> ColorDepth {
+	let term = term.unwrap_or_default();
+	// terminfo has no way of saying a terminal supports 24-bit colors besides the *-direct entries
+	if matches!(colorterm, Some("truecolor") | Some("24bit")) || term.ends_with("-direct") {
+		return ColorDepth::TrueColor;
+	}
+	match terminfo_colors {
+		Some(colors) if colors >= 256 => ColorDepth::Ansi256,
+		Some(_) => ColorDepth::Ansi16,
+		None if term.contains("256color") => ColorDepth::Ansi256,
+		None => ColorDepth::Ansi16,
+	}
+}
+
+/// Reads the `colors` capability from the terminfo entry of `term`
+fn terminfo_colors(term: &str) -> Option<i32> {
+	let first = term.chars().next()?;
+	let mut dirs: Vec<PathBuf> = Vec::new();
+	dirs.extend(env::var_os("TERMINFO").map(PathBuf::from));
+	dirs.extend(env::var_os("HOME").map(|home| PathBuf::from(home).join(".terminfo")));
+	if let Some(terminfo_dirs) = env::var_os("TERMINFO_DIRS") {
+		dirs.extend(env::split_paths(&terminfo_dirs).filter(|dir| !dir.as_os_str().is_empty()));
+	}
+	dirs.extend(["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"].iter().map(PathBuf::from));
+	dirs.iter()
+		// entries are in a directory named after their first letter, or it's hex code on macOS
+		.flat_map(|dir| [dir.join(first.to_string()), dir.join(format!("{:x}", first as u32))])
+		.find_map(|dir| fs::read(dir.join(term)).ok())
+		.and_then(|entry| parse_terminfo_colors(&entry))
+}
+
+/// Finds the `colors` capability in a compiled terminfo entry, see term(5)
+fn parse_terminfo_colors(entry: &[u8]) -> Option<i32> {
+	// index of `colors` in the numeric capabilities
+	const COLORS: usize = 13;
+	let header = |i: usize| entry.get(i * 2..i * 2 + 2).map(|b| i16::from_le_bytes([b[0], b[1]]));
+	let number_size = match header(0)? {
+		0o432 => 2,
+		// the extended format with 32-bit numbers
+		0o1036 => 4,
+		_ => return None,
+	};
+	let (names_size, bools_count, numbers_count) = (header(1)? as usize, header(2)? as usize, header(3)? as usize);
+	if COLORS >= numbers_count {
+		return None;
+	}
+	// numbers are aligned to 2 bytes
+	let numbers_start = (12 + names_size + bools_count).div_ceil(2) * 2;
+	let at = numbers_start + COLORS * number_size;
+	let colors = match number_size {
+		2 => i16::from_le_bytes(entry.get(at..at + 2)?.try_into().ok()?) as i32,
+		_ => i32::from_le_bytes(entry.get(at..at + 4)?.try_into().ok()?),
+	};
+	// -1 means the capability is missing
+	(colors >= 0).then_some(colors)
+}
+
+/// How different two colors look, the "redmean" approximation of perceived distance
+fn distance(a: [f32; 3], b: [u8; 3]) -> f32 {
+	let mean_red = (a[0] + b[0] as f32) / 2.0;
+	let [dr, dg, db] = [a[0] - b[0] as f32, a[1] - b[1] as f32, a[2] - b[2] as f32];
+	(2.0 + mean_red / 256.0) * dr * dr + 4.0 * dg * dg + (2.0 + (255.0 - mean_red) / 256.0) * db * db
+}
+
+fn palette(depth: ColorDepth) -> &'static [(Color, [u8; 3])] {
+	static CUBE: OnceLock<Vec<(Color, [u8; 3])>> = OnceLock::new();
+	match depth {
+		ColorDepth::Ansi16 => &ANSI_16,
+		_ => CUBE.get_or_init(|| {
+			let mut cube = Vec::with_capacity(216);
+			for r in 0..6 {
+				for g in 0..6 {
+					for b in 0..6 {
+						cube.push((Color::RgbLowRes(r, g, b), [CUBE_LEVELS[r as usize], CUBE_LEVELS[g as usize], CUBE_LEVELS[b as usize]]));
+					}
+				}
+			}
+			cube
+		}),
+	}
+}
+
+fn nearest_in(palette: &[(Color, [u8; 3])], value: [f32; 3]) -> (Color, [u8; 3]) {
+	*palette
+		.iter()
+		.min_by(|(_, a), (_, b)| distance(value, *a).total_cmp(&distance(value, *b)))
+		.unwrap()
+}
+
+/// The color the terminal can show that looks the most like `rgb`
+pub fn nearest(rgb: [u8; 3], depth: ColorDepth) -> Color {
+	match depth {
+		ColorDepth::TrueColor => Color::Rgb(rgb[0], rgb[1], rgb[2]),
+		depth => nearest_in(palette(depth), rgb.map(|channel| channel as f32)).0,
+	}
+}
+
+/// Picks a color the terminal can show for every pixel, dithering if there are too few to match them exactly
+pub fn quantize(img: &RgbImage, depth: ColorDepth, dithering: Dithering) -> Vec<Color> {
+	let palette = match depth {
+		ColorDepth::TrueColor => return img.pixels().map(|pixel| Color::Rgb(pixel[0], pixel[1], pixel[2])).collect(),
+		depth => palette(depth),
+	};
+	// roughly the distance between neighbouring colors of the palette
+	let step = match depth {
+		ColorDepth::Ansi16 => 128.0,
+		_ => 40.0,
+	};
+	let values = img.pixels().map(|pixel| pixel.0.map(|channel| channel as f32)).collect();
+	dither::dither(values, img.width() as usize, dithering, step, |value| {
+		let (color, rgb) = nearest_in(palette, value);
+		(color, rgb.map(|channel| channel as f32))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+
+	use cursive::theme::{BaseColor, Color};
+	use image::{Rgb, RgbImage};
+
+	use super::{depth_from, parse_terminfo_colors, quantize};
+	use crate::{config::ColorDepth, views::Dithering};
+
+	#[test]
+	fn test_depth_detection() {
+		assert_eq!(depth_from(Some("truecolor"), Some("xterm-256color"), Some(256)), ColorDepth::TrueColor);
+		assert_eq!(depth_from(None, Some("xterm-direct"), Some(16777216)), ColorDepth::TrueColor);
+		assert_eq!(depth_from(None, Some("tmux-256color"), Some(256)), ColorDepth::Ansi256);
+		assert_eq!(depth_from(None, Some("screen-256color"), None), ColorDepth::Ansi256);
+		assert_eq!(depth_from(None, Some("linux"), Some(8)), ColorDepth::Ansi16);
+		assert_eq!(depth_from(None, None, None), ColorDepth::Ansi16);
+	}
+
+	#[test]
+	fn test_parse_terminfo_colors() {
+		// header, 3 bytes of names, 2 booleans, a padding byte, and 14 numbers with `colors` last
+		let mut entry = Vec::new();
+		for value in [0o432, 3, 2, 14, 0, 0] {
+			entry.extend_from_slice(&(value as i16).to_le_bytes());
+		}
+		entry.extend_from_slice(b"xt\0\x01\x00\0");
+		for i in 0..14 {
+			entry.extend_from_slice(&(if i == 13 { 256i16 } else { -1 }).to_le_bytes());
+		}
+		assert_eq!(parse_terminfo_colors(&entry), Some(256));
+
+		// the 32-bit format
+		let mut entry = Vec::new();
+		for value in [0o1036, 2, 0, 14, 0, 0] {
+			entry.extend_from_slice(&(value as i16).to_le_bytes());
+		}
+		entry.extend_from_slice(b"x\0");
+		for i in 0..14 {
+			entry.extend_from_slice(&(if i == 13 { 0x1000000 } else { -1i32 }).to_le_bytes());
+		}
+		assert_eq!(parse_terminfo_colors(&entry), Some(0x1000000));
+
+		assert_eq!(parse_terminfo_colors(b"not terminfo"), None);
+	}
+
+	#[test]
+	fn test_quantize() {
+		let img = RgbImage::from_fn(3, 1, |x, _| [Rgb([250, 10, 10]), Rgb([0, 0, 0]), Rgb([100, 180, 240])][x as usize]);
+		assert_eq!(
+			quantize(&img, ColorDepth::Ansi16, Dithering::None),
+			vec![Color::Light(BaseColor::Red), Color::Dark(BaseColor::Black), Color::Dark(BaseColor::Cyan)]
+		);
+		assert_eq!(
+			quantize(&img, ColorDepth::Ansi256, Dithering::None),
+			vec![Color::RgbLowRes(5, 0, 0), Color::RgbLowRes(0, 0, 0), Color::RgbLowRes(1, 3, 5)]
+		);
+		assert_eq!(quantize(&img, ColorDepth::TrueColor, Dithering::FloydSteinberg)[2], Color::Rgb(100, 180, 240));
+	}
+
+	#[test]
+	fn test_quantize_dithered() {
+		// an orange between the 16 colors comes out as a single color unless it's dithered
+		let img = RgbImage::from_pixel(8, 8, Rgb([230, 120, 20]));
+		let count_colors = |colors: Vec<Color>| colors.iter().collect::<HashSet<_>>().len();
+		assert_eq!(count_colors(quantize(&img, ColorDepth::Ansi16, Dithering::None)), 1);
+		for dithering in [Dithering::Bayer, Dithering::FloydSteinberg, Dithering::Atkinson] {
+			assert!(count_colors(quantize(&img, ColorDepth::Ansi16, dithering)) > 1, "{:?}", dithering);
+		}
+	}
+}
@@ -0,0 +1,279 @@
+//! A fullscreen viewer for the full size attachments of a thread
+
+use std::sync::Arc;
+
+use cursive::{CbSink, Printer, Vec2, View, direction::Direction, event::{Event, EventResult, Key}, theme::Effect};
+
+use super::{Dithering, ImageSource, ImageView, RenderMode, ScaleMode};
+use crate::{data::AttachmentData, net};
+
+// how much a single key press zooms in or out
+const ZOOM_STEP: f64 = 1.5;
+const MAX_ZOOM: f64 = 16.0;
+// how far a single key press pans, as a fraction of what's visible
+const PAN_STEP: f64 = 0.25;
+
+/// The part of an image that is shown: all of it fitted to the view at a zoom of 1, and less of it, larger, above that
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+	zoom: f64,
+	// the point of the image in the middle of the view in pixels, `None` for the middle of the image
+	center: Option<(f64, f64)>,
+}
+
+impl Default for Viewport {
+	fn default() -> Viewport {
+		Viewport { zoom: 1.0, center: None }
+	}
+}
+
+impl Viewport {
+	/// How many pixels of an image of `size` are visible in a view of `dims` cells
+	fn visible(&self, (width, height): (u32, u32), dims: Vec2) -> (f64, f64) {
+		let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+		// cells are about twice as tall as they're wide
+		let (view_width, view_height) = (dims.x.max(1) as f64, dims.y.max(1) as f64 * 2.0);
+		let scale = f64::min(view_width / width, view_height / height) * self.zoom;
+		((view_width / scale).min(width), (view_height / scale).min(height))
+	}
+
+	/// The center, moved so that the visible part doesn't go past the edges of the image
+	fn clamped_center(&self, (width, height): (u32, u32), (visible_width, visible_height): (f64, f64)) -> (f64, f64) {
+		let (width, height) = (width as f64, height as f64);
+		let (x, y) = self.center.unwrap_or((width / 2.0, height / 2.0));
+		(
+			x.clamp(visible_width / 2.0, (width - visible_width / 2.0).max(visible_width / 2.0)),
+			y.clamp(visible_height / 2.0, (height - visible_height / 2.0).max(visible_height / 2.0)),
+		)
+	}
+
+	/// The rectangle (x, y, width, height) of an image of `size` that is visible in a view of `dims` cells
+	pub fn crop(&self, size: (u32, u32), dims: Vec2) -> (u32, u32, u32, u32) {
+		let visible = self.visible(size, dims);
+		let (x, y) = self.clamped_center(size, visible);
+		let width = (visible.0.round() as u32).clamp(1, size.0.max(1));
+		let height = (visible.1.round() as u32).clamp(1, size.1.max(1));
+		let left = ((x - visible.0 / 2.0).round().max(0.0) as u32).min(size.0.saturating_sub(width));
+		let top = ((y - visible.1 / 2.0).round().max(0.0) as u32).min(size.1.saturating_sub(height));
+		(left, top, width, height)
+	}
+
+	/// Multiplies the zoom by `factor`, keeping the same point in the middle
+	fn zoom(&mut self, factor: f64, size: (u32, u32), dims: Vec2) {
+		self.center = Some(self.clamped_center(size, self.visible(size, dims)));
+		self.zoom = (self.zoom * factor).clamp(1.0, MAX_ZOOM);
+	}
+
+	/// Moves by `PAN_STEP` of the visible part in the direction of `dx` and `dy`
+	fn pan(&mut self, dx: f64, dy: f64, size: (u32, u32), dims: Vec2) {
+		let visible = self.visible(size, dims);
+		let (x, y) = self.clamped_center(size, visible);
+		self.center = Some((x + dx * visible.0 * PAN_STEP, y + dy * visible.1 * PAN_STEP));
+		self.center = Some(self.clamped_center(size, visible));
+	}
+}
+
+/// Shows the full size attachments of a thread one at a time, fitted to the screen.
+/// +/- zoom in and out, the arrow keys pan, n/p go to the next or previous image.
+pub struct ImageViewer {
+	board: String,
+	attachments: Vec<AttachmentData>,
+	current: usize,
+	source: Arc<ImageSource>,
+	viewport: Viewport,
+	render_mode: RenderMode,
+	scale_method: ScaleMode,
+	dithering: Dithering,
+	cb_sink: CbSink,
+	// the render on screen, and the one replacing it once it's done
+	shown: Option<ImageView>,
+	next: Option<ImageView>,
+	shown_size: Vec2,
+	// the space the image was last rendered for
+	dims: Vec2,
+}
+
+impl ImageViewer {
+	/// Creates a viewer showing `attachments[current]` first
+	pub fn new(board: &str, attachments: Vec<AttachmentData>, current: usize, render_mode: RenderMode, scale_method: ScaleMode, dithering: Dithering, cb_sink: CbSink) -> ImageViewer {
+		let source = Arc::new(ImageSource::new(Self::url(board, &attachments[current])));
+		ImageViewer {
+			board: board.to_string(),
+			attachments,
+			current,
+			source,
+			viewport: Viewport::default(),
+			render_mode,
+			scale_method,
+			dithering,
+			cb_sink,
+			shown: None,
+			next: None,
+			shown_size: Vec2::zero(),
+			dims: Vec2::zero(),
+		}
+	}
+
+	/// Videos can't be decoded, so their thumbnail is shown instead
+	fn is_video(attachment: &AttachmentData) -> bool {
+		matches!(attachment.ext.as_str(), ".webm" | ".mp4")
+	}
+
+	fn url(board: &str, attachment: &AttachmentData) -> String {
+		if Self::is_video(attachment) {
+			net::thumbnail_url(board, attachment.tim)
+		} else {
+			net::image_url(board, attachment.tim, &attachment.ext)
+		}
+	}
+
+	/// The size of the current image in pixels, as the API reports it
+	fn image_size(&self) -> (u32, u32) {
+		let attachment = &self.attachments[self.current];
+		let (width, height) = if Self::is_video(attachment) { (attachment.tn_w, attachment.tn_h) } else { (attachment.w, attachment.h) };
+		(width.max(1) as u32, height.max(1) as u32)
+	}
+
+	fn render(&mut self) {
+		if self.dims.x == 0 || self.dims.y == 0 {
+			return;
+		}
+		self.next = Some(ImageView::load_viewport(
+			self.source.clone(),
+			self.viewport,
+			self.dims,
+			self.render_mode,
+			self.scale_method,
+			self.dithering,
+			self.cb_sink.clone(),
+		));
+	}
+
+	fn show(&mut self, index: usize) {
+		if index == self.current || index >= self.attachments.len() {
+			return;
+		}
+		self.current = index;
+		self.source = Arc::new(ImageSource::new(Self::url(&self.board, &self.attachments[index])));
+		self.viewport = Viewport::default();
+		// the previous image would be misleading while the next one loads
+		self.shown = None;
+		self.render();
+	}
+
+	fn status_line(&self) -> String {
+		let attachment = &self.attachments[self.current];
+		format!(
+			"{}{} ({}×{}) {}/{} {:.0}% [q: back, +/-: zoom, arrows: pan, n/p: next/previous]",
+			attachment.filename,
+			attachment.ext,
+			attachment.w,
+			attachment.h,
+			self.current + 1,
+			self.attachments.len(),
+			self.viewport.zoom * 100.0,
+		)
+	}
+}
+
+impl View for ImageViewer {
+	fn draw(&self, printer: &Printer) {
+		let dims = Vec2::new(printer.size.x, printer.size.y.saturating_sub(1));
+		match &self.shown {
+			Some(shown) => shown.draw(&printer.offset(dims.saturating_sub(self.shown_size) / 2).cropped(self.shown_size)),
+			None => printer.print((0, 0), "loading..."),
+		}
+		printer.with_effect(Effect::Reverse, |printer| {
+			printer.print_hline((0, dims.y), printer.size.x, " ");
+			printer.print((0, dims.y), &self.status_line());
+		});
+	}
+
+	fn layout(&mut self, size: Vec2) {
+		// the last line is for the status
+		let dims = Vec2::new(size.x, size.y.saturating_sub(1));
+		if dims != self.dims {
+			self.dims = dims;
+			self.render();
+		}
+		if self.next.as_ref().is_some_and(ImageView::is_loaded) {
+			self.shown = self.next.take();
+		}
+		if let Some(shown) = &mut self.shown {
+			self.shown_size = shown.required_size(dims).or_min(dims);
+		}
+	}
+
+	fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+		constraint
+	}
+
+	fn take_focus(&mut self, _: Direction) -> bool {
+		true
+	}
+
+	fn on_event(&mut self, event: Event) -> EventResult {
+		let (size, dims) = (self.image_size(), self.dims);
+		match event {
+			Event::Char('+') | Event::Char('=') => self.viewport.zoom(ZOOM_STEP, size, dims),
+			Event::Char('-') => self.viewport.zoom(1.0 / ZOOM_STEP, size, dims),
+			Event::Char('0') => self.viewport = Viewport::default(),
+			Event::Key(Key::Left) => self.viewport.pan(-1.0, 0.0, size, dims),
+			Event::Key(Key::Right) => self.viewport.pan(1.0, 0.0, size, dims),
+			Event::Key(Key::Up) => self.viewport.pan(0.0, -1.0, size, dims),
+			Event::Key(Key::Down) => self.viewport.pan(0.0, 1.0, size, dims),
+			Event::Char('n') | Event::Key(Key::PageDown) => {
+				self.show(self.current + 1);
+				return EventResult::Consumed(None);
+			}
+			Event::Char('p') | Event::Key(Key::PageUp) => {
+				self.show(self.current.saturating_sub(1));
+				return EventResult::Consumed(None);
+			}
+			_ => return EventResult::Ignored,
+		}
+		self.render();
+		EventResult::Consumed(None)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use cursive::Vec2;
+
+	use super::Viewport;
+
+	#[test]
+	fn test_viewport() {
+		// cells are twice as tall as they're wide, so this view is square
+		let dims = Vec2::new(50, 25);
+		let size = (1000, 1000);
+		let mut viewport = Viewport::default();
+		assert_eq!(viewport.crop(size, dims), (0, 0, 1000, 1000));
+
+		viewport.zoom(2.0, size, dims);
+		assert_eq!(viewport.crop(size, dims), (250, 250, 500, 500));
+
+		// panning stops at the edges
+		for _ in 0..10 {
+			viewport.pan(1.0, -1.0, size, dims);
+		}
+		assert_eq!(viewport.crop(size, dims), (500, 0, 500, 500));
+
+		// zooming out past the start just shows all of it again
+		viewport.zoom(0.1, size, dims);
+		assert_eq!(viewport.crop(size, dims), (0, 0, 1000, 1000));
+	}
+
+	#[test]
+	fn test_viewport_wide_image() {
+		// only the height is cropped until the image is zoomed past the width of the view
+		let dims = Vec2::new(100, 25);
+		let size = (2000, 500);
+		let mut viewport = Viewport::default();
+		viewport.zoom(1.5, size, dims);
+		assert_eq!(viewport.crop(size, dims), (333, 0, 1333, 500));
+		viewport.zoom(1.5, size, dims);
+		assert_eq!(viewport.crop(size, dims), (556, 28, 889, 444));
+	}
+}
@@ -0,0 +1,382 @@
+use std::{fmt::Display, sync::{Arc, Mutex}, thread, time::Duration};
+
+use chrono::{DateTime, TimeZone, Utc};
+use log::*;
+
+use serde_json::json;
+
+use crate::{config::ThreadConfig, data::{Post, Thread}, html, net, quotes::{self, QuoteLink}};
+
+/// What happened in a watched thread
+#[derive(Debug, Clone)]
+pub enum EventKind {
+	// a post newer than the last one we've seen
+	NewPost(Post),
+	// a new post quoting one of our posts (`to`)
+	Reply { post: Post, to: isize },
+	// the thread was moved to the archive and won't get any new posts
+	Archived,
+	// the thread 404'd, most likely because it was pruned or deleted
+	NotFound,
+}
+
+/// Something the watcher noticed about one of the watched threads
+#[derive(Debug, Clone)]
+pub struct WatchEvent {
+	pub board:  String,
+	pub thread: isize,
+	pub kind:   EventKind,
+}
+
+impl WatchEvent {
+	/// The post this event is about, if it's about a post
+	pub fn post(&self) -> Option<&Post> {
+		match &self.kind {
+			EventKind::NewPost(post) | EventKind::Reply { post, .. } => Some(post),
+			EventKind::Archived | EventKind::NotFound => None,
+		}
+	}
+
+	/// The start of the post's comment on a single line, at most `max_chars` long
+	pub fn excerpt(&self, max_chars: usize) -> Option<String> {
+		let text = html::comment_to_text(self.post()?.com.as_deref()?);
+		let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+		if line.chars().count() <= max_chars {
+			return Some(line);
+		}
+		let mut excerpt: String = line.chars().take(max_chars.saturating_sub(3)).collect();
+		excerpt.push_str("...");
+		Some(excerpt)
+	}
+
+	/// Short machine readable name of the kind of event
+	pub fn name(&self) -> &'static str {
+		match self.kind {
+			EventKind::NewPost(_) => "new_post",
+			EventKind::Reply { .. } => "reply",
+			EventKind::Archived => "archived",
+			EventKind::NotFound => "not_found",
+		}
+	}
+
+	pub fn to_json(&self) -> serde_json::Value {
+		let mut json = json!({ "event": self.name(), "board": self.board, "thread": self.thread });
+		if let Some(post) = self.post() {
+			json["post"] = json!(post.no);
+			json["time"] = json!(post.time);
+			json["name"] = json!(post.name);
+			json["comment"] = json!(post.com.as_deref().map(html::comment_to_text));
+		}
+		if let EventKind::Reply { to, .. } = self.kind {
+			json["to"] = json!(to);
+		}
+		json
+	}
+}
+
+impl Display for WatchEvent {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.kind {
+			EventKind::NewPost(post) => write!(f, "new post >>{} in /{}/{}", post.no, self.board, self.thread),
+			EventKind::Reply { post, to } => write!(f, ">>{} replied to your post >>{} in /{}/{}", post.no, to, self.board, self.thread),
+			EventKind::Archived => write!(f, "/{}/{} was archived", self.board, self.thread),
+			EventKind::NotFound => write!(f, "/{}/{} 404'd", self.board, self.thread),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadStatus {
+	Alive,
+	Archived,
+	NotFound,
+}
+
+/// A watched thread along with everything that happened in it since it was last read
+#[derive(Debug, Clone)]
+pub struct WatchedThread {
+	pub config:  ThreadConfig,
+	pub status:  ThreadStatus,
+	// posts that arrived since the thread was last opened
+	pub unread:  usize,
+	// how many of those quote one of our posts
+	pub replies: usize,
+}
+
+impl WatchedThread {
+	pub fn new(config: ThreadConfig) -> WatchedThread {
+		WatchedThread { config, status: ThreadStatus::Alive, unread: 0, replies: 0 }
+	}
+
+	/// Compares a freshly loaded copy of the thread with what has already been seen.
+	/// A thread that has never been checked before reports nothing, it's current posts all count as seen.
+	pub fn update(&mut self, thread: &Thread) -> Vec<EventKind> {
+		let mut events = Vec::new();
+		let newest = thread.posts.iter().map(|post| post.no).max().unwrap_or(0);
+		if let Some(time) = thread.posts.iter().map(|post| post.time).max() {
+			self.config.last_modified = Utc.timestamp(time as i64, 0);
+		}
+		let last_seen = self.config.last_seen;
+		if last_seen > 0 {
+			for post in thread.posts.iter().filter(|post| post.no > last_seen) {
+				let links = quotes::parse_quotelinks(post.com.as_deref().unwrap_or(""), &self.config.board, self.config.id);
+				let quoted_you = links.iter().find_map(|link| match link {
+					QuoteLink::Post(no) if self.config.you.contains(no) => Some(*no),
+					_ => None,
+				});
+				self.unread += 1;
+				match quoted_you {
+					Some(to) => {
+						self.replies += 1;
+						events.push(EventKind::Reply { post: post.clone(), to });
+					}
+					None => events.push(EventKind::NewPost(post.clone())),
+				}
+			}
+		}
+		self.config.last_seen = self.config.last_seen.max(newest);
+
+		let archived = thread.posts.first()
+			.and_then(|op| op.op_data.as_ref())
+			.is_some_and(|op_data| op_data.archived);
+		if archived && self.status == ThreadStatus::Alive {
+			self.status = ThreadStatus::Archived;
+			events.push(EventKind::Archived);
+		}
+		events
+	}
+
+	/// Records that the thread 404'd. Only reported the first time.
+	pub fn mark_not_found(&mut self) -> Option<EventKind> {
+		if self.status == ThreadStatus::NotFound {
+			return None;
+		}
+		self.status = ThreadStatus::NotFound;
+		Some(EventKind::NotFound)
+	}
+}
+
+/// The watch list, shared between the UI and the thread polling it in the background
+#[derive(Debug, Clone, Default)]
+pub struct Watcher {
+	threads: Arc<Mutex<Vec<WatchedThread>>>,
+}
+
+impl Watcher {
+	pub fn new(configs: Vec<ThreadConfig>) -> Watcher {
+		Watcher { threads: Arc::new(Mutex::new(configs.into_iter().map(WatchedThread::new).collect())) }
+	}
+
+	/// A snapshot of every watched thread
+	pub fn threads(&self) -> Vec<WatchedThread> {
+		self.threads.lock().unwrap().clone()
+	}
+
+	/// The configs of every watched thread, for saving the watch list
+	pub fn configs(&self) -> Vec<ThreadConfig> {
+		self.threads.lock().unwrap().iter().map(|watched| watched.config.clone()).collect()
+	}
+
+	pub fn is_watched(&self, board: &str, thread: isize) -> bool {
+		self.threads.lock().unwrap().iter().any(|watched| watched.config.board == board && watched.config.id == thread)
+	}
+
+	/// Starts watching a thread, unless it already is
+	pub fn add(&self, config: ThreadConfig) {
+		// checked under the same lock as the push, so adding a thread twice at once can't watch it twice
+		let mut threads = self.threads.lock().unwrap();
+		if !threads.iter().any(|watched| watched.config.board == config.board && watched.config.id == config.id) {
+			threads.push(WatchedThread::new(config));
+		}
+	}
+
+	pub fn remove(&self, board: &str, thread: isize) {
+		self.threads.lock().unwrap().retain(|watched| !(watched.config.board == board && watched.config.id == thread));
+	}
+
+	/// Resets the unread counts of a thread after it was opened
+	pub fn mark_read(&self, board: &str, thread: isize) {
+		for watched in self.threads.lock().unwrap().iter_mut() {
+			if watched.config.board == board && watched.config.id == thread {
+				watched.unread = 0;
+				watched.replies = 0;
+			}
+		}
+	}
+
+	/// Marks `post` as one of your own posts in a watched thread so replies to it are reported, or unmarks it if it was.
+	/// Returns whether it's marked now, or `None` if the thread isn't watched.
+	pub fn toggle_you(&self, board: &str, thread: isize, post: isize) -> Option<bool> {
+		let mut threads = self.threads.lock().unwrap();
+		let watched = threads.iter_mut().find(|watched| watched.config.board == board && watched.config.id == thread)?;
+		let you = &mut watched.config.you;
+		match you.iter().position(|&no| no == post) {
+			Some(i) => {
+				you.remove(i);
+				Some(false)
+			}
+			None => {
+				you.push(post);
+				Some(true)
+			}
+		}
+	}
+
+	/// Total number of unread posts in all watched threads
+	pub fn unread(&self) -> usize {
+		self.threads.lock().unwrap().iter().map(|watched| watched.unread).sum()
+	}
+
+	/// Checks every thread that can still get new posts once, passing everything that changed to `on_event`.
+	/// Fails with the last error if not a single thread could be checked.
+	pub fn poll(&self, on_event: &mut impl FnMut(WatchEvent)) -> Result<(), net::Error> {
+		let targets: Vec<(String, isize, DateTime<Utc>)> = self.threads
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|watched| watched.status == ThreadStatus::Alive)
+			.map(|watched| (watched.config.board.clone(), watched.config.id, watched.config.last_modified))
+			.collect();
+		let mut checked = targets.is_empty();
+		let mut last_error = None;
+		for (board, thread, last_modified) in targets {
+			// the list isn't locked during the request, so the UI stays responsive
+			let result = net::get_thread_since(&board, thread, last_modified, net::Priority::Background);
+			let events = {
+				let mut threads = self.threads.lock().unwrap();
+				// the thread might have been removed in the meantime
+				let watched = match threads.iter_mut().find(|watched| watched.config.board == board && watched.config.id == thread) {
+					Some(watched) => watched,
+					None => continue,
+				};
+				match result {
+					Ok(Some(loaded)) => {
+						checked = true;
+						watched.update(&loaded)
+					}
+					// nothing was posted since the last check
+					Ok(None) => {
+						checked = true;
+						Vec::new()
+					}
+					Err(net::Error::NotFound) => {
+						checked = true;
+						watched.mark_not_found().into_iter().collect()
+					}
+					Err(e) => {
+						warn!("Failed to check watched thread /{}/{}: {}", board, thread, e);
+						last_error = Some(e);
+						Vec::new()
+					}
+				}
+			};
+			for kind in events {
+				on_event(WatchEvent { board: board.clone(), thread, kind });
+			}
+		}
+		match last_error {
+			Some(e) if !checked => Err(e),
+			_ => Ok(()),
+		}
+	}
+
+	/// Polls the watch list every `interval` on a background thread
+	pub fn spawn(&self, interval: Duration, mut on_event: impl FnMut(WatchEvent) + Send + 'static) {
+		let watcher = self.clone();
+		thread::spawn(move || loop {
+			// failures are already logged, and the next round might work again
+			let _ = watcher.poll(&mut on_event);
+			thread::sleep(interval);
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{EventKind, ThreadStatus, WatchEvent, WatchedThread, Watcher};
+	use crate::{config::{ThreadConfig, get_unix_epoch}, data::Thread};
+
+	fn load_dummy() -> Thread {
+		let test = std::fs::read_to_string("assets/test/dummy.json").unwrap();
+		serde_json::de::from_str(&test).unwrap()
+	}
+
+	fn watched(last_seen: isize, you: Vec<isize>) -> WatchedThread {
+		WatchedThread::new(ThreadConfig {
+			board: "po".into(),
+			id: 570368,
+			name: "test".into(),
+			last_modified: get_unix_epoch(),
+			last_seen,
+			you,
+		})
+	}
+
+	#[test]
+	fn test_first_check_reports_nothing() {
+		let mut watched = watched(0, Vec::new());
+		assert!(watched.update(&load_dummy()).is_empty());
+		assert_eq!(watched.config.last_seen, 570371);
+		assert_eq!(watched.unread, 0);
+	}
+
+	#[test]
+	fn test_new_posts_and_replies() {
+		let mut thread = load_dummy();
+		thread.posts[2].com = Some("<a href=\"#p570368\" class=\"quotelink\">&gt;&gt;570368</a><br>nice".into());
+		let mut watched = watched(570368, vec![570368]);
+		let events = watched.update(&thread);
+		assert_eq!(events.len(), 2);
+		assert!(matches!(&events[0], EventKind::NewPost(post) if post.no == 570370));
+		assert!(matches!(&events[1], EventKind::Reply { post, to: 570368 } if post.no == 570371));
+		assert_eq!((watched.unread, watched.replies), (2, 1));
+		// nothing is reported twice
+		assert!(watched.update(&thread).is_empty());
+		assert_eq!(watched.unread, 2);
+	}
+
+	#[test]
+	fn test_event_output() {
+		let thread = load_dummy();
+		let event = WatchEvent {
+			board: "po".into(),
+			thread: 570368,
+			kind: EventKind::Reply { post: thread.posts[1].clone(), to: 570368 },
+		};
+		let json = event.to_json();
+		assert_eq!(json["event"], "reply");
+		assert_eq!(json["post"], 570370);
+		assert_eq!(json["to"], 570368);
+		let excerpt = event.excerpt(20).unwrap();
+		assert!(excerpt.chars().count() <= 20 && excerpt.ends_with("..."));
+		assert!(!excerpt.contains('\n'));
+
+		let event = WatchEvent { board: "po".into(), thread: 570368, kind: EventKind::NotFound };
+		assert_eq!(event.to_json()["event"], "not_found");
+		assert!(event.excerpt(20).is_none());
+		assert_eq!(event.to_string(), "/po/570368 404'd");
+	}
+
+	#[test]
+	fn test_toggle_you() {
+		let watcher = Watcher::new(vec![watched(570371, Vec::new()).config]);
+		assert_eq!(watcher.toggle_you("po", 570368, 570370), Some(true));
+		assert_eq!(watcher.configs()[0].you, vec![570370]);
+		assert_eq!(watcher.toggle_you("po", 570368, 570370), Some(false));
+		assert!(watcher.configs()[0].you.is_empty());
+		assert_eq!(watcher.toggle_you("g", 1, 2), None);
+	}
+
+	#[test]
+	fn test_archived_and_not_found() {
+		let mut thread = load_dummy();
+		thread.posts[0].op_data.as_mut().unwrap().archived = true;
+		let mut watched = watched(570371, Vec::new());
+		assert!(matches!(watched.update(&thread)[..], [EventKind::Archived]));
+		assert_eq!(watched.status, ThreadStatus::Archived);
+		assert!(watched.update(&thread).is_empty());
+
+		assert!(matches!(watched.mark_not_found(), Some(EventKind::NotFound)));
+		assert!(watched.mark_not_found().is_none());
+	}
+}